@@ -22,29 +22,38 @@
 //!
 //! [avro-rs]: https://crates.io/crates/avro-rs
 
-use std::io::Cursor;
+use std::collections::{hash_map, HashMap};
+use std::path::Path;
 use std::sync::Arc;
+use std::time::Instant;
 
 use apache_avro::types::Value;
-use apache_avro::{from_avro_datum, Schema};
+use apache_avro::Schema;
 use dashmap::mapref::entry::Entry;
 use dashmap::DashMap;
 use futures::future::{BoxFuture, Shared};
+use futures::io::{AsyncWrite, AsyncWriteExt};
+use futures::stream::{self, StreamExt};
 use futures::FutureExt;
+use serde::de::DeserializeOwned;
 use serde::ser::Serialize;
 use serde_json::value;
 
 use crate::async_impl::schema_registry::{
-    get_referenced_schema, get_schema_by_id_and_type, get_schema_by_subject, SrSettings,
+    get_referenced_schema, get_schema_by_id_and_type, get_schema_by_subject,
+    get_schema_by_subject_and_version, post_schema, SrSettings,
 };
 use crate::avro_common::{
-    get_name, item_to_bytes, replace_reference, values_to_bytes, AvroSchema, DecodeResult,
-    DecodeResultWithSchema,
+    bytes_to_value, check_item_schema, check_payload_len, check_reference_cycle, get_name,
+    inline_references, item_to_bytes, item_to_bytes_into, item_to_datum, load_schemas_from_dir,
+    load_subject_schemas_from_dir, to_single_object, value_to_item, values_to_bytes, AvroSchema,
+    DecodeResult, DecodeResultWithRaw, DecodeResultWithSchema, DecodeTiming, EncodeResult,
 };
 use crate::error::SRCError;
 use crate::schema_registry_common::{
-    get_bytes_result, BytesResult, RegisteredReference, RegisteredSchema, SchemaType,
-    SubjectNameStrategy,
+    get_bytes_result_with_id_encoding, get_bytes_result_without_magic_byte, put_header,
+    BytesResult, IdEncoding, RegisteredReference, RegisteredSchema, SchemaType,
+    SubjectNameStrategy, SubjectNamer, SuppliedSchema,
 };
 
 /// A decoder used to transform bytes to a Value object
@@ -85,10 +94,19 @@ pub struct AvroDecoder<'a> {
     sr_settings: SrSettings,
     direct_cache: DashMap<u32, Arc<AvroSchema>>,
     cache: DashMap<u32, SharedFutureSchema<'a>>,
+    reference_cache: ReferenceCache,
+    strict_length: bool,
+    max_payload_len: Option<usize>,
+    skip_magic_byte: bool,
+    id_encoding: IdEncoding,
 }
 
 type SharedFutureSchema<'a> = Shared<BoxFuture<'a, Result<Arc<AvroSchema>, SRCError>>>;
 
+/// Cache for referenced schema's, keyed by subject and version, so a schema referenced by multiple
+/// schema's is only fetched once.
+type ReferenceCache = Arc<DashMap<(String, u32), RegisteredSchema>>;
+
 impl<'a> AvroDecoder<'a> {
     /// Creates a new decoder which will use the supplied url to fetch the schema's since the schema
     /// needed is encoded in the binary, independent of the SubjectNameStrategy we don't need any
@@ -100,6 +118,52 @@ impl<'a> AvroDecoder<'a> {
             sr_settings,
             direct_cache: DashMap::new(),
             cache: DashMap::new(),
+            reference_cache: Arc::new(DashMap::new()),
+            strict_length: false,
+            max_payload_len: None,
+            skip_magic_byte: false,
+            id_encoding: IdEncoding::ConfluentU32,
+        }
+    }
+    /// When set to true decoding fails when bytes remain after the value is read, by default any
+    /// trailing bytes are ignored.
+    pub fn set_strict_length(&mut self, strict_length: bool) {
+        self.strict_length = strict_length;
+    }
+    /// Sets the maximum length of the bytes to decode, without the magic byte and id. Longer bytes
+    /// fail to decode before the schema is fetched, by default there is no maximum.
+    pub fn set_max_payload_len(&mut self, max_payload_len: usize) {
+        self.max_payload_len = Some(max_payload_len);
+    }
+    /// When set to true the bytes are expected to start with the 4 byte id, without the magic byte,
+    /// as some legacy producers do. This is not the standard framing, so it should only be set for
+    /// topics that only contain such bytes.
+    pub fn set_skip_magic_byte(&mut self, skip_magic_byte: bool) {
+        self.skip_magic_byte = skip_magic_byte;
+    }
+    /// Sets how the id is encoded after the magic byte, by default the 4 byte Confluent encoding.
+    pub fn set_id_encoding(&mut self, id_encoding: IdEncoding) {
+        self.id_encoding = id_encoding;
+    }
+    /// Adds the schemas read from the `.avsc` files in the directory to the cache, so decoding
+    /// bytes with one of those ids doesn't need the schema registry. See
+    /// [load_schemas_from_dir](crate::avro_common::load_schemas_from_dir) for the file layout.
+    /// Returns the number of schemas added. For encoding, see
+    /// [AvroEncoder::add_fixed_schemas_from_dir].
+    pub fn add_schemas_from_dir(&self, dir: &Path) -> Result<usize, SRCError> {
+        let schemas = load_schemas_from_dir(dir)?;
+        let count = schemas.len();
+        for schema in schemas {
+            self.direct_cache.insert(schema.id, Arc::new(schema));
+        }
+        Ok(count)
+    }
+    /// Splits the bytes into the id and the rest, depending on whether the magic byte is skipped.
+    fn bytes_result(&self, bytes: Option<&[u8]>) -> BytesResult {
+        if self.skip_magic_byte {
+            get_bytes_result_without_magic_byte(bytes)
+        } else {
+            get_bytes_result_with_id_encoding(bytes, self.id_encoding)
         }
     }
     /// Remove al the errors from the cache, you might need to/want to run this when a recoverable
@@ -126,7 +190,7 @@ impl<'a> AvroDecoder<'a> {
     ///
     /// let heartbeat = decoder.decode(Some(&bytes)).await;
     ///
-    /// assert_eq!(heartbeat, Err(SRCError::new("Could not get raw schema from response", None, false).into_cache()));
+    /// assert_eq!(heartbeat, Err(SRCError::not_found("schema registry responded with status 404 Not Found", Some(String::from(r#"{"error_code":40403,"message":"Schema not found"}"#))).into_cache()));
     ///
     /// let _m = server .mock("GET", "/schemas/ids/2?deleted=true")
     ///     .with_status(200)
@@ -135,7 +199,7 @@ impl<'a> AvroDecoder<'a> {
     ///     .create();
     ///
     /// let heartbeat = decoder.decode(Some(&bytes)).await;
-    /// assert_eq!(heartbeat, Err(SRCError::new("Could not get raw schema from response", None, false).into_cache()));
+    /// assert_eq!(heartbeat, Err(SRCError::not_found("schema registry responded with status 404 Not Found", Some(String::from(r#"{"error_code":40403,"message":"Schema not found"}"#))).into_cache()));
     ///
     /// decoder.remove_errors_from_cache();
     ///
@@ -171,7 +235,7 @@ impl<'a> AvroDecoder<'a> {
     /// }
     /// ```
     pub async fn decode(&self, bytes: Option<&[u8]>) -> Result<DecodeResult, SRCError> {
-        match get_bytes_result(bytes) {
+        match self.bytes_result(bytes) {
             BytesResult::Null => Ok(DecodeResult {
                 name: None,
                 value: Value::Null,
@@ -186,17 +250,52 @@ impl<'a> AvroDecoder<'a> {
     /// The actual deserialization trying to get the id from the bytes to retrieve the schema, and
     /// using a reader transforms the bytes to a value.
     async fn deserialize(&self, id: u32, bytes: &[u8]) -> Result<DecodeResult, SRCError> {
+        check_payload_len(bytes, self.max_payload_len)?;
         let schema = self.get_schema(id).await?;
-        let mut reader = Cursor::new(bytes);
-        match from_avro_datum(&schema.parsed, &mut reader, None) {
-            Ok(v) => Ok(DecodeResult {
-                name: get_name(&schema.parsed),
-                value: v,
-            }),
-            Err(e) => Err(SRCError::non_retryable_with_cause(
-                e,
-                "Could not transform bytes using schema",
+        Ok(DecodeResult {
+            name: get_name(&schema.parsed),
+            value: bytes_to_value(&schema.parsed, None, bytes, self.strict_length)?,
+        })
+    }
+    /// Decodes bytes into a value, like decode, but also returns the time spend getting the schema
+    /// and decoding the bytes, and whether the schema was already cached.
+    pub async fn decode_timed(
+        &self,
+        bytes: Option<&[u8]>,
+    ) -> Result<(DecodeResult, DecodeTiming), SRCError> {
+        match self.bytes_result(bytes) {
+            BytesResult::Null => Ok((
+                DecodeResult {
+                    name: None,
+                    value: Value::Null,
+                },
+                DecodeTiming::default(),
             )),
+            BytesResult::Valid(id, bytes) => {
+                check_payload_len(&bytes, self.max_payload_len)?;
+                let cache_hit = self.direct_cache.contains_key(&id);
+                let start = Instant::now();
+                let schema = self.get_schema(id).await?;
+                let fetch = start.elapsed();
+                let start = Instant::now();
+                let value = bytes_to_value(&schema.parsed, None, &bytes, self.strict_length)?;
+                let decode = start.elapsed();
+                Ok((
+                    DecodeResult {
+                        name: get_name(&schema.parsed),
+                        value,
+                    },
+                    DecodeTiming {
+                        fetch,
+                        decode,
+                        cache_hit,
+                    },
+                ))
+            }
+            BytesResult::Invalid(bytes) => Err(SRCError::non_retryable_without_cause(&format!(
+                "Invalid bytes {:?}",
+                bytes
+            ))),
         }
     }
     /// Decodes bytes into a DecodeResultWithSchema.
@@ -208,7 +307,7 @@ impl<'a> AvroDecoder<'a> {
         &self,
         bytes: Option<&[u8]>,
     ) -> Result<Option<DecodeResultWithSchema>, SRCError> {
-        match get_bytes_result(bytes) {
+        match self.bytes_result(bytes) {
             BytesResult::Null => Ok(None),
             BytesResult::Valid(id, bytes) => match self.deserialize_with_schema(id, &bytes).await {
                 Ok(v) => Ok(Some(v)),
@@ -220,6 +319,172 @@ impl<'a> AvroDecoder<'a> {
             ))),
         }
     }
+    /// Decodes bytes into a value, like decode, but also returns a copy of the bytes without the
+    /// magic byte and id, for example to store them for auditing or to produce them again.
+    pub async fn decode_with_raw(
+        &self,
+        bytes: Option<&[u8]>,
+    ) -> Result<Option<DecodeResultWithRaw>, SRCError> {
+        match self.bytes_result(bytes) {
+            BytesResult::Null => Ok(None),
+            BytesResult::Valid(id, bytes) => {
+                let DecodeResult { name, value } = self.deserialize(id, &bytes).await?;
+                Ok(Some(DecodeResultWithRaw {
+                    name,
+                    value,
+                    raw: bytes,
+                }))
+            }
+            BytesResult::Invalid(bytes) => Err(SRCError::non_retryable_without_cause(&format!(
+                "Invalid bytes {:?}",
+                bytes
+            ))),
+        }
+    }
+    /// Decodes bytes into a value like decode, but resolves the value against the reader schema.
+    /// This makes it possible to read data written with an older or newer version of the schema,
+    /// for example fields added with a default get the default value. The name is taken from the
+    /// reader schema.
+    pub async fn decode_with_reader_schema(
+        &self,
+        bytes: Option<&[u8]>,
+        reader_schema: &Schema,
+    ) -> Result<DecodeResult, SRCError> {
+        match self.bytes_result(bytes) {
+            BytesResult::Null => Ok(DecodeResult {
+                name: None,
+                value: Value::Null,
+            }),
+            BytesResult::Valid(id, bytes) => {
+                check_payload_len(&bytes, self.max_payload_len)?;
+                let schema = self.get_schema(id).await?;
+                Ok(DecodeResult {
+                    name: get_name(reader_schema),
+                    value: bytes_to_value(
+                        &schema.parsed,
+                        Some(reader_schema),
+                        &bytes,
+                        self.strict_length,
+                    )?,
+                })
+            }
+            BytesResult::Invalid(bytes) => Err(SRCError::non_retryable_without_cause(&format!(
+                "Invalid bytes {:?}",
+                bytes
+            ))),
+        }
+    }
+    /// Decodes bytes that might already have the magic byte and id removed. When the bytes start
+    /// with the magic byte, and are long enough to contain an id, they are decoded like with decode.
+    /// Otherwise they are decoded using the schema with the fallback id, which is required in that
+    /// case. Since unframed bytes might also start with a zero, this should only be used when
+    /// unframed values never do, for example when they are records starting with a non zero field.
+    pub async fn decode_auto_framed(
+        &self,
+        bytes: Option<&[u8]>,
+        fallback_id: Option<u32>,
+    ) -> Result<Option<DecodeResult>, SRCError> {
+        match (self.bytes_result(bytes), fallback_id) {
+            (BytesResult::Null, _) => Ok(None),
+            (BytesResult::Valid(id, bytes), _) => Ok(Some(self.deserialize(id, &bytes).await?)),
+            (BytesResult::Invalid(bytes), Some(id)) => {
+                Ok(Some(self.deserialize(id, &bytes).await?))
+            }
+            (BytesResult::Invalid(bytes), None) => {
+                Err(SRCError::non_retryable_without_cause(&format!(
+                    "Bytes {:?} are not framed, and no fallback id was supplied",
+                    bytes
+                )))
+            }
+        }
+    }
+    /// Decodes bytes without magic byte and id, using the schema with the given id. This is useful
+    /// when the id is transported separately, for example in a Kafka header. Returns none when the
+    /// bytes are none.
+    pub async fn decode_unframed(
+        &self,
+        id: u32,
+        bytes: Option<&[u8]>,
+    ) -> Result<Option<DecodeResult>, SRCError> {
+        match bytes {
+            None => Ok(None),
+            Some(bytes) => Ok(Some(self.deserialize(id, bytes).await?)),
+        }
+    }
+    /// Decodes many bytes, like decode, returning the results in the same order. The schema for
+    /// each distinct id is only looked up once for the whole batch, which saves work when draining
+    /// a partition where most of the values share the same schema.
+    pub async fn decode_batch(
+        &self,
+        items: &[Option<&[u8]>],
+    ) -> Vec<Result<DecodeResult, SRCError>> {
+        let mut schemas: HashMap<u32, Result<Arc<AvroSchema>, SRCError>> = HashMap::new();
+        let mut results = Vec::with_capacity(items.len());
+        for bytes in items {
+            let result = match self.bytes_result(*bytes) {
+                BytesResult::Null => Ok(DecodeResult {
+                    name: None,
+                    value: Value::Null,
+                }),
+                BytesResult::Valid(id, bytes) => {
+                    let schema = match schemas.entry(id) {
+                        hash_map::Entry::Occupied(e) => e.into_mut(),
+                        hash_map::Entry::Vacant(e) => e.insert(self.get_schema(id).await),
+                    };
+                    schema
+                        .clone()
+                        .and_then(|schema| self.deserialize_with(&schema, &bytes))
+                }
+                BytesResult::Invalid(bytes) => Err(SRCError::non_retryable_without_cause(
+                    &format!("Invalid bytes {:?}", bytes),
+                )),
+            };
+            results.push(result);
+        }
+        results
+    }
+    /// Deserializes the bytes with an already retrieved schema.
+    fn deserialize_with(
+        &self,
+        schema: &AvroSchema,
+        bytes: &[u8],
+    ) -> Result<DecodeResult, SRCError> {
+        check_payload_len(bytes, self.max_payload_len)?;
+        Ok(DecodeResult {
+            name: get_name(&schema.parsed),
+            value: bytes_to_value(&schema.parsed, None, bytes, self.strict_length)?,
+        })
+    }
+    /// Decodes bytes into an item, for example a struct generated from the schema, using serde.
+    /// Returns none when the bytes are none or empty, so tombstones on compacted topics can be
+    /// handled without inspecting the bytes first.
+    pub async fn decode_struct<T: DeserializeOwned>(
+        &self,
+        bytes: Option<&[u8]>,
+    ) -> Result<Option<T>, SRCError> {
+        match bytes {
+            None | Some([]) => Ok(None),
+            Some(_) => {
+                let result = self.decode(bytes).await?;
+                Ok(Some(value_to_item(&result.value)?))
+            }
+        }
+    }
+    /// Decodes bytes into a value, like decode, but also returns the id of the schema that was used,
+    /// which is read from the bytes. Returns none when the bytes are none.
+    pub async fn decode_with_id(
+        &self,
+        bytes: Option<&[u8]>,
+    ) -> Result<Option<(u32, DecodeResult)>, SRCError> {
+        match self.bytes_result(bytes) {
+            BytesResult::Null => Ok(None),
+            BytesResult::Valid(id, bytes) => Ok(Some((id, self.deserialize(id, &bytes).await?))),
+            BytesResult::Invalid(bytes) => Err(SRCError::non_retryable_without_cause(&format!(
+                "Invalid bytes {:?}",
+                bytes
+            ))),
+        }
+    }
     /// The actual deserialization trying to get the id from the bytes to retrieve the schema, and
     /// using a reader transforms the bytes to a value.
     async fn deserialize_with_schema(
@@ -227,24 +492,49 @@ impl<'a> AvroDecoder<'a> {
         id: u32,
         bytes: &[u8],
     ) -> Result<DecodeResultWithSchema, SRCError> {
+        check_payload_len(bytes, self.max_payload_len)?;
         let schema = self.get_schema(id).await?;
-        let mut reader = Cursor::new(bytes);
-        match from_avro_datum(&schema.parsed, &mut reader, None) {
-            Ok(value) => Ok(DecodeResultWithSchema {
-                name: get_name(&schema.parsed),
-                value,
-                schema,
-            }),
-            Err(e) => Err(SRCError::non_retryable_with_cause(
-                e,
-                "Could not transform bytes using schema",
-            )),
+        Ok(DecodeResultWithSchema {
+            name: get_name(&schema.parsed),
+            value: bytes_to_value(&schema.parsed, None, bytes, self.strict_length)?,
+            schema,
+        })
+    }
+
+    /// Fetches the schemas for the ids, so they are cached before the first messages are decoded.
+    /// At most max_concurrent_requests schemas are fetched at the same time. When supplied, the
+    /// progress function is called with the number of handled ids and the total after each fetch.
+    /// All ids are handled, when one or more failed the first error is returned.
+    pub async fn warm_cache(
+        &self,
+        ids: &[u32],
+        max_concurrent_requests: usize,
+        progress: Option<&(dyn Fn(usize, usize) + Sync)>,
+    ) -> Result<(), SRCError> {
+        let total = ids.len();
+        let mut fetches = stream::iter(ids)
+            .map(|id| self.get_schema(*id))
+            .buffer_unordered(max_concurrent_requests.max(1));
+        let mut done = 0;
+        let mut result = Ok(());
+        while let Some(fetched) = fetches.next().await {
+            done += 1;
+            if let Some(f) = progress {
+                f(done, total)
+            }
+            if let (Err(e), Ok(())) = (fetched, &result) {
+                result = Err(e)
+            }
         }
+        result
     }
 
     async fn get_schema(&self, id: u32) -> Result<Arc<AvroSchema>, SRCError> {
         match self.direct_cache.get(&id) {
             None => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(id, "schema cache miss");
+                self.sr_settings.observe_cache_miss();
                 let result = self.get_schema_by_shared_future(id).await;
                 if result.is_ok() && !self.direct_cache.contains_key(&id) {
                     self.direct_cache.insert(id, result.clone().unwrap());
@@ -252,7 +542,12 @@ impl<'a> AvroDecoder<'a> {
                 };
                 result
             }
-            Some(result) => Ok(result.value().clone()),
+            Some(result) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(id, "schema cache hit");
+                self.sr_settings.observe_cache_hit();
+                Ok(result.value().clone())
+            }
         }
     }
 
@@ -261,10 +556,11 @@ impl<'a> AvroDecoder<'a> {
             Entry::Occupied(e) => e.get().clone(),
             Entry::Vacant(e) => {
                 let sr_settings = self.sr_settings.clone();
+                let reference_cache = self.reference_cache.clone();
                 let v = async move {
                     match get_schema_by_id_and_type(id, &sr_settings, SchemaType::Avro).await {
                         Ok(registered_schema) => {
-                            to_avro_schema(&sr_settings, registered_schema).await
+                            to_avro_schema(&sr_settings, &reference_cache, registered_schema).await
                         }
                         Err(e) => Err(e.into_cache()),
                     }
@@ -330,6 +626,11 @@ pub struct AvroEncoder<'a> {
     sr_settings: SrSettings,
     direct_cache: DashMap<String, Arc<AvroSchema>>,
     cache: DashMap<String, SharedFutureSchema<'a>>,
+    version_direct_cache: DashMap<(String, u32), Arc<AvroSchema>>,
+    version_cache: DashMap<(String, u32), SharedFutureSchema<'a>>,
+    reference_cache: ReferenceCache,
+    auto_register: bool,
+    id_encoding: IdEncoding,
 }
 
 impl<'a> AvroEncoder<'a> {
@@ -374,7 +675,48 @@ impl<'a> AvroEncoder<'a> {
             sr_settings,
             direct_cache: DashMap::new(),
             cache: DashMap::new(),
+            version_direct_cache: DashMap::new(),
+            version_cache: DashMap::new(),
+            reference_cache: Arc::new(DashMap::new()),
+            auto_register: false,
+            id_encoding: IdEncoding::ConfluentU32,
+        }
+    }
+    /// When set to true encode_struct_auto_register may register schemas, by default it's disabled
+    /// so schemas are not registered by accident.
+    pub fn set_auto_register(&mut self, auto_register: bool) {
+        self.auto_register = auto_register;
+    }
+    /// Sets how the id is encoded after the magic byte, by default the 4 byte Confluent encoding.
+    pub fn set_id_encoding(&mut self, id_encoding: IdEncoding) {
+        self.id_encoding = id_encoding;
+    }
+    /// Uses the schema with the id for the subject, without getting it from the schema registry.
+    /// This way a producer that only writes one schema doesn't depend on the schema registry being
+    /// available. The id is put in the bytes as is, so it should be the id the schema registry
+    /// has for the schema. References are not supported.
+    pub fn add_fixed_schema(&self, subject: &str, id: u32, schema: &str) -> Result<(), SRCError> {
+        let parsed = Schema::parse_str(schema).map_err(|e| {
+            SRCError::non_retryable_with_cause(e, &format!("could not parse schema {}", schema))
+        })?;
+        let avro_schema = Arc::new(AvroSchema {
+            id,
+            raw: String::from(schema),
+            parsed,
+        });
+        self.direct_cache.insert(String::from(subject), avro_schema);
+        Ok(())
+    }
+    /// Uses the schemas read from the `.avsc` files in the directory for their subjects, like
+    /// [AvroEncoder::add_fixed_schema] does for a single schema. See
+    /// [load_subject_schemas_from_dir] for the file layout. Returns the number of schemas added.
+    pub fn add_fixed_schemas_from_dir(&self, dir: &Path) -> Result<usize, SRCError> {
+        let schemas = load_subject_schemas_from_dir(dir)?;
+        let count = schemas.len();
+        for (subject, schema) in schemas {
+            self.direct_cache.insert(subject, Arc::new(schema));
         }
+        Ok(count)
     }
     /// Remove al the errors from the cache, you might need to/want to run this when a recoverable
     /// error is met. Errors are also cashed to prevent trying to get schema's that either don't
@@ -400,7 +742,7 @@ impl<'a> AvroEncoder<'a> {
     ///     .create();
     ///
     /// let bytes = encoder.encode(vec![("beat", Value::Long(3))], strategy.clone()).await;
-    /// assert_eq!(bytes, Err(SRCError::new("Could not get id from response", None, false).into_cache()));
+    /// assert_eq!(bytes, Err(SRCError::not_found("schema registry responded with status 404 Not Found", Some(String::from(r#"{"error_code":40403,"message":"Schema not found"}"#))).into_cache()));
     ///
     /// let _m = server .mock("GET", "/subjects/nl.openweb.data.Heartbeat/versions/latest")
     ///     .with_status(200)
@@ -409,7 +751,7 @@ impl<'a> AvroEncoder<'a> {
     ///     .create();
     ///
     /// let bytes = encoder.encode(vec![("beat", Value::Long(3))], strategy.clone()).await;
-    /// assert_eq!(bytes, Err(SRCError::new("Could not get id from response", None, false).into_cache()));
+    /// assert_eq!(bytes, Err(SRCError::not_found("schema registry responded with status 404 Not Found", Some(String::from(r#"{"error_code":40403,"message":"Schema not found"}"#))).into_cache()));
     ///
     /// encoder.remove_errors_from_cache();
     ///
@@ -423,6 +765,10 @@ impl<'a> AvroEncoder<'a> {
             Some(r) => r.is_ok(),
             None => true,
         });
+        self.version_cache.retain(|_, v| match v.peek() {
+            Some(r) => r.is_ok(),
+            None => true,
+        });
     }
     /// Encodes a vector of values to bytes. The correct values of the 'keys' depend on the schema
     /// being fetched at runtime, or the one supplied with the SubjectNameStrategy.
@@ -462,7 +808,22 @@ impl<'a> AvroEncoder<'a> {
             .get_schema_and_id_by_shared_future(key, subject_name_strategy)
             .clone()
             .await?;
-        values_to_bytes(&schema, values)
+        values_to_bytes(&schema, values, self.id_encoding)
+    }
+
+    /// Like encode, but using a specific version of the schema registered for the subject, instead
+    /// of the latest one, or the schema supplied with the SubjectNameStrategy.
+    pub async fn encode_with_version(
+        &self,
+        values: Vec<(&str, Value)>,
+        subject_name_strategy: SubjectNameStrategy,
+        version: u32,
+    ) -> Result<Vec<u8>, SRCError> {
+        let key = subject_name_strategy.get_subject()?;
+        let schema = self
+            .get_schema_for_version(key, subject_name_strategy, version)
+            .await?;
+        values_to_bytes(&schema, values, self.id_encoding)
     }
 
     /// Encodes a struct or a primitive value to bytes. The schema used for the encoding will be
@@ -522,7 +883,233 @@ impl<'a> AvroEncoder<'a> {
         let schema = self
             .get_schema_and_id(&key, subject_name_strategy.clone())
             .await?;
-        item_to_bytes(&schema, item)
+        item_to_bytes(&schema, item, self.id_encoding)
+    }
+
+    /// Like encode_struct, but with an optional item. When the item is none an empty vector is
+    /// returned without fetching the schema, which is produced as a tombstone, for example to
+    /// delete a key from a compacted topic.
+    pub async fn encode_struct_optional(
+        &self,
+        item: Option<impl Serialize>,
+        subject_name_strategy: &SubjectNameStrategy,
+    ) -> Result<Vec<u8>, SRCError> {
+        match item {
+            None => Ok(Vec::new()),
+            Some(item) => self.encode_struct(item, subject_name_strategy).await,
+        }
+    }
+
+    /// Like encode_struct, but first checking whether the schema derived for the item, for example
+    /// with `#[derive(AvroSchema)]`, is compatible with the schema from the schema registry. This
+    /// catches a renamed field or a changed type, which could otherwise give bytes that can't be
+    /// read with the schema. The check is done for each call, so it's best used in tests, or for
+    /// low volume topics.
+    pub async fn encode_struct_verified<T: Serialize + apache_avro::AvroSchema>(
+        &self,
+        item: T,
+        subject_name_strategy: &SubjectNameStrategy,
+    ) -> Result<Vec<u8>, SRCError> {
+        let key = subject_name_strategy.get_subject()?;
+        let schema = self
+            .get_schema_and_id(&key, subject_name_strategy.clone())
+            .await?;
+        check_item_schema(&T::get_schema(), &schema)?;
+        item_to_bytes(&schema, item, self.id_encoding)
+    }
+
+    /// Like encode_struct, but using the subject computed by the SubjectNamer, for the topic and
+    /// the fully qualified name of the record. The latest schema registered for the subject is
+    /// used.
+    pub async fn encode_struct_with_namer(
+        &self,
+        item: impl Serialize,
+        subject_namer: &dyn SubjectNamer,
+        topic: &str,
+        record: &str,
+        is_key: bool,
+    ) -> Result<Vec<u8>, SRCError> {
+        let subject = subject_namer.subject(topic, record, is_key);
+        self.encode_struct(item, &SubjectNameStrategy::RecordNameStrategy(subject))
+            .await
+    }
+
+    /// Like encode_struct, but also returning the subject computed from the SubjectNameStrategy and
+    /// the id of the schema that was used, which can be used for logging.
+    pub async fn encode_struct_full(
+        &self,
+        item: impl Serialize,
+        subject_name_strategy: &SubjectNameStrategy,
+    ) -> Result<EncodeResult, SRCError> {
+        let subject = subject_name_strategy.get_subject()?;
+        let schema = self
+            .get_schema_and_id(&subject, subject_name_strategy.clone())
+            .await?;
+        let bytes = item_to_bytes(&schema, item, self.id_encoding)?;
+        Ok(EncodeResult {
+            subject,
+            id: schema.id,
+            bytes,
+        })
+    }
+
+    /// Like encode_struct, but appending the bytes to the supplied buffer instead of returning a
+    /// new vector. This way the same buffer can be reused, by clearing it before each encode.
+    pub async fn encode_struct_into(
+        &self,
+        item: impl Serialize,
+        subject_name_strategy: &SubjectNameStrategy,
+        buffer: &mut Vec<u8>,
+    ) -> Result<(), SRCError> {
+        let key = subject_name_strategy.get_subject()?;
+        let schema = self
+            .get_schema_and_id(&key, subject_name_strategy.clone())
+            .await?;
+        item_to_bytes_into(&schema, item, buffer, self.id_encoding)
+    }
+
+    /// Like encode_struct, but writing the magic byte, the id, and the encoded bytes to the writer
+    /// instead of returning them. The item is still encoded into a vector first, only the header
+    /// is written separately, so the header doesn't need to be copied in front of the encoded bytes.
+    pub async fn encode_struct_to_writer<W: AsyncWrite + Unpin>(
+        &self,
+        item: impl Serialize,
+        subject_name_strategy: &SubjectNameStrategy,
+        writer: &mut W,
+    ) -> Result<(), SRCError> {
+        let key = subject_name_strategy.get_subject()?;
+        let schema = self
+            .get_schema_and_id(&key, subject_name_strategy.clone())
+            .await?;
+        let datum = item_to_datum(&schema, item)?;
+        let mut header = Vec::with_capacity(5);
+        put_header(schema.id, self.id_encoding, &mut header);
+        let written = match writer.write_all(&header).await {
+            Ok(()) => writer.write_all(&datum).await,
+            Err(e) => Err(e),
+        };
+        match written {
+            Ok(()) => Ok(()),
+            Err(e) => Err(SRCError::non_retryable_with_cause(
+                e,
+                "Could not write the encoded bytes to the writer",
+            )),
+        }
+    }
+
+    /// Like encode_struct, but using avro single object encoding instead of the schema registry
+    /// framing. The bytes start with a marker and the fingerprint of the schema, instead of the id.
+    pub async fn encode_struct_single_object(
+        &self,
+        item: impl Serialize,
+        subject_name_strategy: &SubjectNameStrategy,
+    ) -> Result<Vec<u8>, SRCError> {
+        let key = subject_name_strategy.get_subject()?;
+        let schema = self
+            .get_schema_and_id(&key, subject_name_strategy.clone())
+            .await?;
+        let datum = item_to_datum(&schema, item)?;
+        Ok(to_single_object(&schema.parsed, datum))
+    }
+
+    /// Like encode_struct, but using a specific version of the schema registered for the subject,
+    /// instead of the latest one, or the schema supplied with the SubjectNameStrategy.
+    pub async fn encode_struct_with_version(
+        &self,
+        item: impl Serialize,
+        subject_name_strategy: &SubjectNameStrategy,
+        version: u32,
+    ) -> Result<Vec<u8>, SRCError> {
+        let key = subject_name_strategy.get_subject()?;
+        let schema = self
+            .get_schema_for_version(key, subject_name_strategy.clone(), version)
+            .await?;
+        item_to_bytes(&schema, item, self.id_encoding)
+    }
+
+    /// Like encode_struct, but registering the local schema for the subject of the
+    /// SubjectNameStrategy when the subject has no schema yet. When the subject already has a
+    /// schema the latest one is used. This is meant for development, and needs to be enabled with
+    /// set_auto_register.
+    pub async fn encode_struct_auto_register(
+        &self,
+        item: impl Serialize,
+        subject_name_strategy: &SubjectNameStrategy,
+        local_schema: SuppliedSchema,
+    ) -> Result<Vec<u8>, SRCError> {
+        if !self.auto_register {
+            return Err(SRCError::non_retryable_without_cause(
+                "Auto registering schemas is not enabled, use set_auto_register to enable it",
+            ));
+        }
+        let subject = subject_name_strategy.get_subject()?;
+        let latest = SubjectNameStrategy::RecordNameStrategy(subject.clone());
+        let schema = match self.get_schema_and_id(&subject, latest).await {
+            Ok(schema) => schema,
+            Err(e) if e.is_not_found() => {
+                self.cache.remove(&subject);
+                let registered_schema =
+                    post_schema(&self.sr_settings, subject.clone(), local_schema).await?;
+                let schema =
+                    to_avro_schema(&self.sr_settings, &self.reference_cache, registered_schema)
+                        .await?;
+                self.direct_cache.insert(subject, schema.clone());
+                schema
+            }
+            Err(e) => return Err(e),
+        };
+        item_to_bytes(&schema, item, self.id_encoding)
+    }
+
+    /// Gets the schema registered for the subject, parsed with all the references resolved. When no
+    /// version is supplied the latest version is used. This might be used by tooling, for example to
+    /// inspect the fields. The result is cached, like with encoding.
+    pub async fn get_parsed_schema(
+        &self,
+        subject: &str,
+        version: Option<u32>,
+    ) -> Result<Arc<AvroSchema>, SRCError> {
+        let strategy = SubjectNameStrategy::RecordNameStrategy(String::from(subject));
+        match version {
+            None => self.get_schema_and_id(subject, strategy).await,
+            Some(v) => {
+                self.get_schema_for_version(String::from(subject), strategy, v)
+                    .await
+            }
+        }
+    }
+
+    /// Gets the schema for a specific version of the subject. Pinned versions are cached apart from
+    /// the latest schemas, keyed by the subject and the version.
+    async fn get_schema_for_version(
+        &self,
+        subject: String,
+        subject_name_strategy: SubjectNameStrategy,
+        version: u32,
+    ) -> Result<Arc<AvroSchema>, SRCError> {
+        let key = (subject, version);
+        match self.version_direct_cache.get(&key) {
+            None => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(subject = key.0, version, "schema cache miss");
+                self.sr_settings.observe_cache_miss();
+                let result = self
+                    .get_schema_for_version_by_shared_future(key.clone(), subject_name_strategy)
+                    .await;
+                if result.is_ok() && !self.version_direct_cache.contains_key(&key) {
+                    self.version_direct_cache
+                        .insert(key.clone(), result.clone().unwrap());
+                    self.version_cache.remove(&key);
+                };
+                result
+            }
+            Some(result) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(subject = key.0, version, "schema cache hit");
+                self.sr_settings.observe_cache_hit();
+                Ok(result.value().clone())
+            }
+        }
     }
 
     pub async fn get_schema_and_id(
@@ -532,6 +1119,9 @@ impl<'a> AvroEncoder<'a> {
     ) -> Result<Arc<AvroSchema>, SRCError> {
         match self.direct_cache.get(key) {
             None => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(subject = key, "schema cache miss");
+                self.sr_settings.observe_cache_miss();
                 let result = self
                     .get_schema_and_id_by_shared_future(key.to_string(), subject_name_strategy)
                     .await;
@@ -542,7 +1132,12 @@ impl<'a> AvroEncoder<'a> {
                 };
                 result
             }
-            Some(result) => Ok(result.value().clone()),
+            Some(result) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(subject = key, "schema cache hit");
+                self.sr_settings.observe_cache_hit();
+                Ok(result.value().clone())
+            }
         }
     }
 
@@ -553,26 +1148,59 @@ impl<'a> AvroEncoder<'a> {
     ) -> SharedFutureSchema<'a> {
         match self.cache.entry(key) {
             Entry::Occupied(e) => e.get().clone(),
-            Entry::Vacant(e) => {
-                let sr_settings = self.sr_settings.clone();
-                let v = async move {
-                    match get_schema_by_subject(&sr_settings, &subject_name_strategy).await {
-                        Ok(registered_schema) => {
-                            to_avro_schema(&sr_settings, registered_schema).await
-                        }
-                        Err(e) => Err(e.into_cache()),
-                    }
+            Entry::Vacant(e) => e
+                .insert(self.schema_future(subject_name_strategy, None))
+                .value()
+                .clone(),
+        }
+    }
+
+    fn get_schema_for_version_by_shared_future(
+        &self,
+        key: (String, u32),
+        subject_name_strategy: SubjectNameStrategy,
+    ) -> SharedFutureSchema<'a> {
+        let version = key.1;
+        match self.version_cache.entry(key) {
+            Entry::Occupied(e) => e.get().clone(),
+            Entry::Vacant(e) => e
+                .insert(self.schema_future(subject_name_strategy, Some(version)))
+                .value()
+                .clone(),
+        }
+    }
+
+    /// Creates the shared future getting the schema for the subject, the latest one when no version
+    /// is supplied, and parsing it with the references.
+    fn schema_future(
+        &self,
+        subject_name_strategy: SubjectNameStrategy,
+        version: Option<u32>,
+    ) -> SharedFutureSchema<'a> {
+        let sr_settings = self.sr_settings.clone();
+        let reference_cache = self.reference_cache.clone();
+        async move {
+            let registered_schema = match version {
+                None => get_schema_by_subject(&sr_settings, &subject_name_strategy).await,
+                Some(v) => {
+                    get_schema_by_subject_and_version(&sr_settings, &subject_name_strategy, v).await
                 }
-                .boxed()
-                .shared();
-                e.insert(v).value().clone()
+            };
+            match registered_schema {
+                Ok(registered_schema) => {
+                    to_avro_schema(&sr_settings, &reference_cache, registered_schema).await
+                }
+                Err(e) => Err(e.into_cache()),
             }
         }
+        .boxed()
+        .shared()
     }
 }
 
 async fn to_avro_schema(
     sr_settings: &SrSettings,
+    reference_cache: &DashMap<(String, u32), RegisteredSchema>,
     registered_schema: RegisteredSchema,
 ) -> Result<Arc<AvroSchema>, SRCError> {
     match registered_schema.schema_type {
@@ -586,7 +1214,14 @@ async fn to_avro_schema(
     }
     let main_schema = match serde_json::from_str(&registered_schema.schema) {
         Ok(v) => {
-            match add_references(sr_settings, v, registered_schema.references.as_slice()).await {
+            match add_references(
+                sr_settings,
+                reference_cache,
+                v,
+                registered_schema.references.as_slice(),
+            )
+            .await
+            {
                 Ok(u) => u,
                 Err(e) => return Err(e),
             }
@@ -614,23 +1249,51 @@ async fn to_avro_schema(
     }
 }
 
-fn add_references<'a>(
-    sr_settings: &'a SrSettings,
+async fn add_references(
+    sr_settings: &SrSettings,
+    reference_cache: &DashMap<(String, u32), RegisteredSchema>,
     json_value: value::Value,
+    references: &[RegisteredReference],
+) -> Result<value::Value, SRCError> {
+    let mut collected = Vec::new();
+    collect_references(
+        sr_settings,
+        reference_cache,
+        references,
+        &[],
+        &mut collected,
+    )
+    .await?;
+    let children: Vec<value::Value> = collected.into_iter().map(|(_, v)| v).collect();
+    Ok(inline_references(json_value, &children))
+}
+
+/// Collects the schemas of the references, and of their references, each subject and version only
+/// once.
+fn collect_references<'a>(
+    sr_settings: &'a SrSettings,
+    reference_cache: &'a DashMap<(String, u32), RegisteredSchema>,
     references: &'a [RegisteredReference],
-) -> BoxFuture<'a, Result<value::Value, SRCError>> {
+    resolving: &'a [(String, u32)],
+    collected: &'a mut Vec<((String, u32), value::Value)>,
+) -> BoxFuture<'a, Result<(), SRCError>> {
     async move {
-        let mut new_value = json_value;
         for r in references.iter() {
-            let registered_schema = match get_referenced_schema(sr_settings, r).await {
-                Ok(v) => v,
-                Err(e) => {
-                    return Err(SRCError::non_retryable_with_cause(
-                        e,
-                        &format!("problem with reference {:?}", r),
-                    ));
-                }
-            };
+            check_reference_cycle(resolving, r)?;
+            let key = (r.subject.clone(), r.version);
+            if collected.iter().any(|(k, _)| *k == key) {
+                continue;
+            }
+            let registered_schema =
+                match get_cached_referenced_schema(sr_settings, reference_cache, r).await {
+                    Ok(v) => v,
+                    Err(e) => {
+                        return Err(SRCError::non_retryable_with_cause(
+                            e,
+                            &format!("problem with reference {:?}", r),
+                        ));
+                    }
+                };
             let child: value::Value = match serde_json::from_str(&registered_schema.schema) {
                 Ok(v) => v,
                 Err(e) => {
@@ -640,26 +1303,52 @@ fn add_references<'a>(
                     ));
                 }
             };
-            new_value = replace_reference(new_value, child);
-            new_value =
-                match add_references(sr_settings, new_value, &registered_schema.references).await {
-                    Ok(v) => v,
-                    Err(e) => return Err(e),
-                }
+            collected.push((key.clone(), child));
+            let mut path = resolving.to_vec();
+            path.push(key);
+            collect_references(
+                sr_settings,
+                reference_cache,
+                &registered_schema.references,
+                &path,
+                collected,
+            )
+            .await?;
         }
-        Ok(new_value)
+        Ok(())
     }
     .boxed()
 }
 
-#[cfg(test)]
-mod tests {
-    use apache_avro::from_value;
+async fn get_cached_referenced_schema(
+    sr_settings: &SrSettings,
+    reference_cache: &DashMap<(String, u32), RegisteredSchema>,
+    registered_reference: &RegisteredReference,
+) -> Result<RegisteredSchema, SRCError> {
+    let key = (
+        registered_reference.subject.clone(),
+        registered_reference.version,
+    );
+    if let Some(registered_schema) = reference_cache.get(&key) {
+        return Ok(registered_schema.value().clone());
+    }
+    let registered_schema = get_referenced_schema(sr_settings, registered_reference).await?;
+    reference_cache.insert(key, registered_schema.clone());
+    Ok(registered_schema)
+}
+
+#[cfg(test)]
+mod tests {
+    use apache_avro::from_value;
 
     use crate::avro_common::get_supplied_schema;
-    use crate::schema_registry_common::SuppliedSchema;
+    use crate::schema_registry_common::{ErrorClassifier, Observer, SuppliedSchema};
 
     use super::*;
+    use crate::avro_common::{get_fingerprint, SingleObjectDecoder};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+    use std::time::Duration;
     use test_utils::Heartbeat;
 
     #[test]
@@ -667,7 +1356,7 @@ mod tests {
         let sr_settings = SrSettings::new(String::from("http://127.0.0.1:1234"));
         let decoder = AvroDecoder::new(sr_settings);
         assert_eq!(
-            "AvroDecoder { sr_settings: SrSettings { urls: [\"http://127.0.0.1:1234\"], client: Client { accepts: Accepts, proxies: [Proxy(System({}), None)], referer: true, default_headers: {\"accept\": \"*/*\"} }, authorization: None }, direct_cache: {}, cache: {} }"
+            "AvroDecoder { sr_settings: SrSettings { urls: [\"http://127.0.0.1:1234\"], authorization: None }, direct_cache: {}, cache: {}, reference_cache: {}, strict_length: false, max_payload_len: None, skip_magic_byte: false, id_encoding: ConfluentU32 }"
                 .to_owned(),
             format!("{:?}", decoder)
         )
@@ -727,84 +1416,142 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_decoder_no_bytes() {
-        let sr_settings = SrSettings::new(String::from("http://127.0.0.1:1234"));
-        let decoder = AvroDecoder::new(sr_settings);
-        let heartbeat = decoder.decode(None).await.unwrap().value;
-
-        assert_eq!(heartbeat, Value::Null)
-    }
+    async fn test_decode_timed() {
+        let mut server = mockito::Server::new_async().await;
+        let _m = server.mock("GET", "/schemas/ids/1?deleted=true")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"schema":"{\"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
+            .expect(1)
+            .create();
 
-    #[tokio::test]
-    async fn test_decoder_with_name_no_bytes() {
-        let sr_settings = SrSettings::new(String::from("http://127.0.0.1:1234"));
+        let sr_settings = SrSettings::new(server.url());
         let decoder = AvroDecoder::new(sr_settings);
-        let heartbeat = decoder.decode(None).await.unwrap();
+        let (first, first_timing) = decoder
+            .decode_timed(Some(&[0, 0, 0, 0, 1, 6]))
+            .await
+            .unwrap();
+        let (second, second_timing) = decoder
+            .decode_timed(Some(&[0, 0, 0, 0, 1, 6]))
+            .await
+            .unwrap();
 
         assert_eq!(
-            heartbeat,
-            DecodeResult {
-                name: None,
-                value: Value::Null
-            }
-        )
+            first.value,
+            Value::Record(vec![("beat".to_string(), Value::Long(3))])
+        );
+        assert_eq!(first, second);
+        assert!(!first_timing.cache_hit);
+        assert!(first_timing.fetch > Duration::ZERO);
+        assert!(second_timing.cache_hit);
+        _m.assert();
     }
 
     #[tokio::test]
-    async fn test_decoder_magic_byte_not_present() {
-        let sr_settings = SrSettings::new(String::from("http://127.0.0.1:1234"));
-        let decoder = AvroDecoder::new(sr_settings);
-        let result = decoder.decode(Some(&[1, 0, 0, 0, 1, 6])).await.unwrap_err();
-
-        assert_eq!(
-            result,
-            SRCError::non_retryable_without_cause("Invalid bytes [1, 0, 0, 0, 1, 6]")
-        )
-    }
+    async fn test_decode_with_raw() {
+        let mut server = mockito::Server::new_async().await;
+        let _m = server.mock("GET", "/schemas/ids/1?deleted=true")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"schema":"{\"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
+            .create();
 
-    #[tokio::test]
-    async fn test_decoder_with_name_magic_byte_not_present() {
-        let sr_settings = SrSettings::new(String::from("http://127.0.0.1:1234"));
+        let sr_settings = SrSettings::new(server.url());
         let decoder = AvroDecoder::new(sr_settings);
-        let result = decoder.decode(Some(&[1, 0, 0, 0, 1, 6])).await.unwrap_err();
+        let result = decoder
+            .decode_with_raw(Some(&[0, 0, 0, 0, 1, 6]))
+            .await
+            .unwrap()
+            .unwrap();
 
         assert_eq!(
-            result,
-            SRCError::non_retryable_without_cause("Invalid bytes [1, 0, 0, 0, 1, 6]")
-        )
+            result.value,
+            Value::Record(vec![("beat".to_string(), Value::Long(3))])
+        );
+        assert_eq!(result.raw, vec![6]);
+        assert_eq!(decoder.decode_with_raw(None).await, Ok(None));
     }
 
     #[tokio::test]
-    async fn test_decoder_not_enough_bytes() {
-        let sr_settings = SrSettings::new(String::from("http://127.0.0.1:1234"));
+    async fn test_warm_cache() {
+        let mut server = mockito::Server::new_async().await;
+        let mut mocks = Vec::new();
+        for id in 1..=5 {
+            mocks.push(server.mock("GET", format!("/schemas/ids/{}?deleted=true", id).as_str())
+                .with_status(200)
+                .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+                .with_body(r#"{"schema":"{\"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
+                .expect(1)
+                .create());
+        }
+
+        let sr_settings = SrSettings::new(server.url());
         let decoder = AvroDecoder::new(sr_settings);
-        let result = decoder.decode(Some(&[0, 0, 0, 0])).await.unwrap_err();
+        let in_flight = AtomicUsize::new(0);
+        let reported = Mutex::new(Vec::new());
+        let progress = |done: usize, total: usize| reported.lock().unwrap().push((done, total));
+        let fetches = decoder.warm_cache(&[1, 2, 3, 4, 5], 2, Some(&progress));
+        let max_in_flight = async {
+            let mut max = 0;
+            while decoder.direct_cache.len() < 5 {
+                max = max.max(decoder.cache.len());
+                in_flight.store(max, Ordering::SeqCst);
+                tokio::task::yield_now().await;
+            }
+        };
+        let (result, _) = tokio::join!(fetches, max_in_flight);
 
+        result.unwrap();
+        assert!((1..=2).contains(&in_flight.load(Ordering::SeqCst)));
         assert_eq!(
-            result,
-            SRCError::non_retryable_without_cause("Invalid bytes [0, 0, 0, 0]")
-        )
+            vec![(1, 5), (2, 5), (3, 5), (4, 5), (5, 5)],
+            *reported.lock().unwrap()
+        );
+        let heartbeat = decoder.decode(Some(&[0, 0, 0, 0, 4, 6])).await.unwrap();
+        assert_eq!(
+            heartbeat.value,
+            Value::Record(vec![("beat".to_string(), Value::Long(3))])
+        );
+        for m in mocks {
+            m.assert_async().await;
+        }
     }
 
     #[tokio::test]
-    async fn test_decoder_wrong_data() {
-        let mut server = mockito::Server::new();
+    async fn test_decode_trailing_bytes() {
+        let mut server = mockito::Server::new_async().await;
         let _m = server.mock("GET", "/schemas/ids/1?deleted=true")
             .with_status(200)
             .with_header("content-type", "application/vnd.schemaregistry.v1+json")
             .with_body(r#"{"schema":"{\"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
             .create();
 
+        let bytes = [0, 0, 0, 0, 1, 6, 0, 0];
         let sr_settings = SrSettings::new(server.url());
-        let decoder = AvroDecoder::new(sr_settings);
-        let error = decoder.decode(Some(&[0, 0, 0, 0, 1])).await.unwrap_err();
+        let mut decoder = AvroDecoder::new(sr_settings);
+        let heartbeat = decoder.decode(Some(&bytes)).await.unwrap().value;
+        assert_eq!(
+            heartbeat,
+            Value::Record(vec![("beat".to_string(), Value::Long(3))])
+        );
 
-        assert_eq!(error.error, "Could not transform bytes using schema")
+        decoder.set_strict_length(true);
+        let error = decoder.decode(Some(&bytes)).await.unwrap_err();
+        assert_eq!(
+            error.error,
+            "2 bytes remained after transforming the bytes using schema"
+        );
+        assert!(!error.retriable);
+        let heartbeat = decoder.decode(Some(&bytes[..6])).await.unwrap().value;
+        assert_eq!(
+            heartbeat,
+            Value::Record(vec![("beat".to_string(), Value::Long(3))])
+        );
     }
 
     #[tokio::test]
-    async fn test_decoder_with_name_wrong_data() {
-        let mut server = mockito::Server::new();
+    async fn test_decode_skip_magic_byte() {
+        let mut server = mockito::Server::new_async().await;
         let _m = server.mock("GET", "/schemas/ids/1?deleted=true")
             .with_status(200)
             .with_header("content-type", "application/vnd.schemaregistry.v1+json")
@@ -812,184 +1559,637 @@ mod tests {
             .create();
 
         let sr_settings = SrSettings::new(server.url());
-        let decoder = AvroDecoder::new(sr_settings);
-        let error = decoder.decode(Some(&[0, 0, 0, 0, 1])).await.unwrap_err();
+        let mut decoder = AvroDecoder::new(sr_settings);
+        decoder.set_skip_magic_byte(true);
 
-        assert_eq!(error.error, "Could not transform bytes using schema")
+        let heartbeat = decoder.decode(Some(&[0, 0, 0, 1, 6])).await.unwrap().value;
+        assert_eq!(
+            heartbeat,
+            Value::Record(vec![("beat".to_string(), Value::Long(3))])
+        );
     }
 
     #[tokio::test]
-    async fn test_decoder_no_json_response() {
-        let mut server = mockito::Server::new();
+    async fn test_decode_with_max_payload_len() {
+        let sr_settings = SrSettings::new(String::from("http://127.0.0.1:1234"));
+        let mut decoder = AvroDecoder::new(sr_settings);
+        decoder.set_max_payload_len(1);
+
+        let error = decoder
+            .decode(Some(&[0, 0, 0, 0, 1, 6, 0]))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.error,
+            "payload of 2 bytes is larger than the maximum of 1 bytes"
+        );
+        assert!(!error.retriable);
+    }
+
+    #[tokio::test]
+    async fn test_decode_with_reader_schema() {
+        let mut server = mockito::Server::new_async().await;
         let _m = server.mock("GET", "/schemas/ids/1?deleted=true")
             .with_status(200)
             .with_header("content-type", "application/vnd.schemaregistry.v1+json")
-            .with_body(r#"{"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
+            .with_body(r#"{"schema":"{\"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
             .create();
 
+        let reader_schema = Schema::parse_str(r#"{"type":"record","name":"Heartbeat","namespace":"nl.openweb.data","fields":[{"name":"beat","type":"long"},{"name":"source","type":"string","default":"unknown"}]}"#).unwrap();
         let sr_settings = SrSettings::new(server.url());
         let decoder = AvroDecoder::new(sr_settings);
-        let heartbeat = decoder.decode(Some(&[0, 0, 0, 0, 1, 6])).await.unwrap_err();
 
+        let result = decoder
+            .decode_with_reader_schema(Some(&[0, 0, 0, 0, 1, 6]), &reader_schema)
+            .await
+            .unwrap();
         assert_eq!(
-            heartbeat,
-            SRCError::new(
-                "could not parse to RawRegisteredSchema, schema might not exist on this schema registry, the http call failed, cause will give more information",
-                Some(String::from(
-                    "error decoding response body: expected `:` at line 1 column 130"
-                )),
-                false,
-            )
-            .into_cache()
-        )
+            result.value,
+            Value::Record(vec![
+                ("beat".to_string(), Value::Long(3)),
+                ("source".to_string(), Value::String("unknown".to_string())),
+            ])
+        );
     }
 
     #[tokio::test]
-    async fn test_decoder_with_name_no_json_response() {
-        let mut server = mockito::Server::new();
+    async fn test_decode_struct() {
+        let mut server = mockito::Server::new_async().await;
         let _m = server.mock("GET", "/schemas/ids/1?deleted=true")
             .with_status(200)
             .with_header("content-type", "application/vnd.schemaregistry.v1+json")
-            .with_body(r#"{"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
+            .with_body(r#"{"schema":"{\"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
             .create();
 
         let sr_settings = SrSettings::new(server.url());
         let decoder = AvroDecoder::new(sr_settings);
-        let heartbeat = decoder.decode(Some(&[0, 0, 0, 0, 1, 6])).await.unwrap_err();
-
-        assert_eq!(
-            heartbeat,
-            SRCError::new(
-                "could not parse to RawRegisteredSchema, schema might not exist on this schema registry, the http call failed, cause will give more information",
-                Some(String::from(
-                    "error decoding response body: expected `:` at line 1 column 130"
-                )),
-                false,
-            )
-            .into_cache()
-        )
-    }
 
-    #[tokio::test]
-    async fn test_decoder_schema_registry_unavailable() {
-        let sr_settings = SrSettings::new(String::from("http://bogus"));
-        let decoder = AvroDecoder::new(sr_settings);
-        let err = decoder
-            .decode(Some(&[0, 0, 0, 10, 1, 6]))
+        let item = decoder
+            .decode_struct::<Heartbeat>(Some(&[0, 0, 0, 0, 1, 6]))
+            .await;
+        assert_eq!(item.unwrap().unwrap().beat, 3);
+        assert!(decoder
+            .decode_struct::<Heartbeat>(None)
             .await
-            .unwrap_err();
-        assert_eq!(err.error, "http call to schema registry failed")
+            .unwrap()
+            .is_none());
+        assert!(decoder
+            .decode_struct::<Heartbeat>(Some(&[]))
+            .await
+            .unwrap()
+            .is_none());
     }
 
     #[tokio::test]
-    async fn test_decoder_default_no_schema_in_response() {
-        let mut server = mockito::Server::new();
+    async fn test_decode_batch() {
+        let mut server = mockito::Server::new_async().await;
         let _m = server.mock("GET", "/schemas/ids/1?deleted=true")
             .with_status(200)
             .with_header("content-type", "application/vnd.schemaregistry.v1+json")
-            .with_body(r#"{"no-schema":"{\"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
+            .with_body(r#"{"schema":"{\"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
+            .expect(1)
             .create();
 
         let sr_settings = SrSettings::new(server.url());
         let decoder = AvroDecoder::new(sr_settings);
-        let err = decoder.decode(Some(&[0, 0, 0, 0, 1, 6])).await.unwrap_err();
+        let first: &[u8] = &[0, 0, 0, 0, 1, 6];
+        let second: &[u8] = &[0, 0, 0, 0, 1, 8];
+        let invalid: &[u8] = &[1, 6];
+
+        let results = decoder
+            .decode_batch(&[Some(first), None, Some(invalid), Some(second)])
+            .await;
 
+        _m.assert();
+        assert_eq!(results.len(), 4);
         assert_eq!(
-            err,
-            SRCError::new("Could not get raw schema from response", None, false).into_cache()
-        )
+            results[0].as_ref().unwrap().value,
+            Value::Record(vec![("beat".to_string(), Value::Long(3))])
+        );
+        assert_eq!(results[1].as_ref().unwrap().value, Value::Null);
+        assert!(results[2].is_err());
+        assert_eq!(
+            results[3].as_ref().unwrap().value,
+            Value::Record(vec![("beat".to_string(), Value::Long(4))])
+        );
     }
 
     #[tokio::test]
-    async fn test_decoder_default_wrong_schema_in_response() {
-        let mut server = mockito::Server::new();
+    async fn test_decode_unframed() {
+        let mut server = mockito::Server::new_async().await;
         let _m = server.mock("GET", "/schemas/ids/1?deleted=true")
             .with_status(200)
             .with_header("content-type", "application/vnd.schemaregistry.v1+json")
-            .with_body(r#"{"schema":"{\"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\"}"}"#)
+            .with_body(r#"{"schema":"{\"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
             .create();
 
         let sr_settings = SrSettings::new(server.url());
         let decoder = AvroDecoder::new(sr_settings);
-        let err = decoder.decode(Some(&[0, 0, 0, 0, 1, 6])).await.unwrap_err();
 
+        let result = decoder
+            .decode_unframed(1, Some(&[6]))
+            .await
+            .unwrap()
+            .unwrap();
         assert_eq!(
-            err.error,
-           "Supplied raw value \"{\\\"type\\\":\\\"record\\\",\\\"name\\\":\\\"Heartbeat\\\",\\\"namespace\\\":\\\"nl.openweb.data\\\"}\" cant be turned into a Schema",
-        )
+            result.value,
+            Value::Record(vec![("beat".to_string(), Value::Long(3))])
+        );
+        assert_eq!(decoder.decode_unframed(1, None).await, Ok(None));
     }
 
     #[tokio::test]
-    async fn test_decoder_fixed_with_enum() {
-        let mut server = mockito::Server::new();
-        let _m = server.mock("GET", "/schemas/ids/6?deleted=true")
+    async fn test_decode_auto_framed() {
+        let mut server = mockito::Server::new_async().await;
+        let _m = server.mock("GET", "/schemas/ids/1?deleted=true")
             .with_status(200)
             .with_header("content-type", "application/vnd.schemaregistry.v1+json")
-            .with_body(r#"{"schema":"{\"type\":\"record\",\"name\":\"ConfirmAccountCreation\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"id\",\"type\":{\"type\":\"fixed\",\"name\":\"Uuid\",\"size\":16}},{\"name\":\"a_type\",\"type\":{\"type\":\"enum\",\"name\":\"Atype\",\"symbols\":[\"AUTO\",\"MANUAL\"]}}]}"}"#)
+            .with_body(r#"{"schema":"{\"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
             .create();
 
         let sr_settings = SrSettings::new(server.url());
         let decoder = AvroDecoder::new(sr_settings);
-        let cac = decoder
-            .decode(Some(&[
-                0, 0, 0, 0, 6, 204, 240, 237, 74, 227, 188, 75, 46, 183, 163, 122, 214, 178, 72,
-                118, 162, 2,
-            ]))
+        let expected = Value::Record(vec![("beat".to_string(), Value::Long(3))]);
+
+        let framed = decoder
+            .decode_auto_framed(Some(&[0, 0, 0, 0, 1, 6]), None)
             .await
             .unwrap()
-            .value;
-
+            .unwrap();
+        assert_eq!(framed.value, expected);
+        let unframed = decoder
+            .decode_auto_framed(Some(&[6]), Some(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(unframed.value, expected);
+        let error = decoder
+            .decode_auto_framed(Some(&[6]), None)
+            .await
+            .unwrap_err();
         assert_eq!(
-            cac,
-            Value::Record(vec![
-                (
-                    "id".to_string(),
-                    Value::Fixed(
-                        16,
-                        vec![
-                            204, 240, 237, 74, 227, 188, 75, 46, 183, 163, 122, 214, 178, 72, 118,
-                            162
-                        ],
-                    )
-                ),
-                ("a_type".to_string(), Value::Enum(1, "MANUAL".to_string()))
-            ])
+            error.error,
+            "Bytes [6] are not framed, and no fallback id was supplied"
         );
+        assert_eq!(decoder.decode_auto_framed(None, Some(1)).await, Ok(None));
     }
 
     #[tokio::test]
-    async fn test_decoder_cache() {
-        let mut server = mockito::Server::new();
-        let sr_settings = SrSettings::new(server.url());
-        let decoder = AvroDecoder::new(sr_settings);
-        let bytes = [0, 0, 0, 0, 2, 6];
-
-        let _m = server
-            .mock("GET", "/schemas/ids/2?deleted=true")
-            .with_status(404)
-            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
-            .with_body(r#"{"error_code":40403,"message":"Schema not found"}"#)
-            .create();
-        let err = decoder.decode(Some(&bytes)).await.unwrap_err();
-        assert_eq!(
-            err,
-            SRCError::new("Could not get raw schema from response", None, false).into_cache()
-        );
-        let _m = server.mock("GET", "/schemas/ids/2?deleted=true")
+    async fn test_decode_auto_framed_apicurio_id_encoding() {
+        let mut server = mockito::Server::new_async().await;
+        let _m = server.mock("GET", "/schemas/ids/1?deleted=true")
             .with_status(200)
             .with_header("content-type", "application/vnd.schemaregistry.v1+json")
             .with_body(r#"{"schema":"{\"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
             .create();
 
-        let err = decoder.decode(Some(&bytes)).await.unwrap_err();
+        let mut decoder = AvroDecoder::new(SrSettings::new(server.url()));
+        decoder.set_id_encoding(IdEncoding::ApicurioU64);
+
+        let framed = decoder
+            .decode_auto_framed(Some(&[0, 0, 0, 0, 0, 0, 0, 0, 1, 6]), None)
+            .await
+            .unwrap()
+            .unwrap();
         assert_eq!(
-            err,
-            SRCError::new("Could not get raw schema from response", None, false).into_cache()
+            framed.value,
+            Value::Record(vec![("beat".to_string(), Value::Long(3))])
         );
+    }
 
-        decoder.remove_errors_from_cache();
+    #[derive(Debug, Default)]
+    struct CountingObserver {
+        hits: AtomicUsize,
+        misses: AtomicUsize,
+        fetches: Mutex<Vec<bool>>,
+    }
+
+    impl Observer for CountingObserver {
+        fn on_cache_hit(&self) {
+            self.hits.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn on_cache_miss(&self) {
+            self.misses.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn on_fetch(&self, _duration: Duration, succeeded: bool) {
+            self.fetches.lock().unwrap().push(succeeded);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_decode_notifies_observer() {
+        let mut server = mockito::Server::new_async().await;
+        let _m = server.mock("GET", "/schemas/ids/1?deleted=true")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"schema":"{\"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
+            .create();
+
+        let observer = Arc::new(CountingObserver::default());
+        let sr_settings = SrSettings::new_builder(server.url())
+            .set_observer(observer.clone())
+            .build()
+            .unwrap();
+        let decoder = AvroDecoder::new(sr_settings);
+        decoder.decode(Some(&[0, 0, 0, 0, 1, 6])).await.unwrap();
+        decoder.decode(Some(&[0, 0, 0, 0, 1, 6])).await.unwrap();
+
+        assert_eq!(observer.misses.load(Ordering::SeqCst), 1);
+        assert_eq!(observer.hits.load(Ordering::SeqCst), 1);
+        assert_eq!(*observer.fetches.lock().unwrap(), vec![true]);
+    }
+
+    #[tokio::test]
+    async fn test_pinned_version_cached_apart_from_subject() {
+        let mut server = mockito::Server::new_async().await;
+        let _v = server.mock("GET", "/subjects/orders/versions/3")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"subject":"orders","version":3,"id":3,"schema":"{\"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
+            .expect(1)
+            .create();
+        let _l = server.mock("GET", "/subjects/orders%2Fversions%2F3/versions/latest")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"subject":"orders/versions/3","version":1,"id":4,"schema":"{\"type\":\"string\"}"}"#)
+            .expect(1)
+            .create();
+
+        let observer = Arc::new(CountingObserver::default());
+        let sr_settings = SrSettings::new_builder(server.url())
+            .set_observer(observer.clone())
+            .build()
+            .unwrap();
+        let encoder = AvroEncoder::new(sr_settings);
+
+        let pinned = encoder.get_parsed_schema("orders", Some(3)).await.unwrap();
+        let latest = encoder
+            .get_parsed_schema("orders/versions/3", None)
+            .await
+            .unwrap();
+        let again = encoder.get_parsed_schema("orders", Some(3)).await.unwrap();
+
+        assert_eq!(pinned.id, 3);
+        assert_eq!(latest.id, 4);
+        assert!(Arc::ptr_eq(&pinned, &again));
+        assert_eq!(observer.misses.load(Ordering::SeqCst), 2);
+        assert_eq!(observer.hits.load(Ordering::SeqCst), 1);
+        _v.assert();
+        _l.assert();
+    }
+
+    #[derive(Debug)]
+    struct NotFoundIsRetryable;
+
+    impl ErrorClassifier for NotFoundIsRetryable {
+        fn is_retryable(&self, status: reqwest::StatusCode) -> bool {
+            status == reqwest::StatusCode::NOT_FOUND || status.is_server_error()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_decode_retry_with_error_classifier() {
+        let mut server = mockito::Server::new_async().await;
+        let not_found = server
+            .mock("GET", "/schemas/ids/1?deleted=true")
+            .with_status(404)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"error_code":40403,"message":"Schema not found"}"#)
+            .expect(1)
+            .create();
+
+        let sr_settings = SrSettings::new_builder(server.url())
+            .set_error_classifier(Arc::new(NotFoundIsRetryable))
+            .build()
+            .unwrap();
+        let decoder = AvroDecoder::new(sr_settings);
+        let error = decoder.decode(Some(&[0, 0, 0, 0, 1, 6])).await.unwrap_err();
+        assert!(error.is_retryable());
+        not_found.assert();
+        not_found.remove_async().await;
+
+        let found = server.mock("GET", "/schemas/ids/1?deleted=true")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"schema":"{\"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
+            .expect(1)
+            .create();
+        decoder.remove_errors_from_cache();
+        let heartbeat = decoder
+            .decode(Some(&[0, 0, 0, 0, 1, 6]))
+            .await
+            .unwrap()
+            .value;
+        assert_eq!(
+            heartbeat,
+            Value::Record(vec![("beat".to_string(), Value::Long(3))])
+        );
+        found.assert();
+    }
+
+    #[tokio::test]
+    async fn test_decode_with_id() {
+        let mut server = mockito::Server::new_async().await;
+        let _m = server.mock("GET", "/schemas/ids/4?deleted=true")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"schema":"{\"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
+            .create();
+
+        let sr_settings = SrSettings::new(server.url());
+        let decoder = AvroDecoder::new(sr_settings);
+        let (id, result) = decoder
+            .decode_with_id(Some(&[0, 0, 0, 0, 4, 6]))
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(id, 4);
+        assert_eq!(
+            result.value,
+            Value::Record(vec![("beat".to_string(), Value::Long(3))])
+        );
+        assert_eq!(decoder.decode_with_id(None).await, Ok(None));
+    }
+
+    #[tokio::test]
+    async fn test_decoder_no_bytes() {
+        let sr_settings = SrSettings::new(String::from("http://127.0.0.1:1234"));
+        let decoder = AvroDecoder::new(sr_settings);
+        let heartbeat = decoder.decode(None).await.unwrap().value;
+
+        assert_eq!(heartbeat, Value::Null)
+    }
+
+    #[tokio::test]
+    async fn test_decoder_with_name_no_bytes() {
+        let sr_settings = SrSettings::new(String::from("http://127.0.0.1:1234"));
+        let decoder = AvroDecoder::new(sr_settings);
+        let heartbeat = decoder.decode(None).await.unwrap();
+
+        assert_eq!(
+            heartbeat,
+            DecodeResult {
+                name: None,
+                value: Value::Null
+            }
+        )
+    }
+
+    #[tokio::test]
+    async fn test_decoder_magic_byte_not_present() {
+        let sr_settings = SrSettings::new(String::from("http://127.0.0.1:1234"));
+        let decoder = AvroDecoder::new(sr_settings);
+        let result = decoder.decode(Some(&[1, 0, 0, 0, 1, 6])).await.unwrap_err();
+
+        assert_eq!(
+            result,
+            SRCError::non_retryable_without_cause("Invalid bytes [1, 0, 0, 0, 1, 6]")
+        )
+    }
+
+    #[tokio::test]
+    async fn test_decoder_with_name_magic_byte_not_present() {
+        let sr_settings = SrSettings::new(String::from("http://127.0.0.1:1234"));
+        let decoder = AvroDecoder::new(sr_settings);
+        let result = decoder.decode(Some(&[1, 0, 0, 0, 1, 6])).await.unwrap_err();
+
+        assert_eq!(
+            result,
+            SRCError::non_retryable_without_cause("Invalid bytes [1, 0, 0, 0, 1, 6]")
+        )
+    }
+
+    #[tokio::test]
+    async fn test_decoder_not_enough_bytes() {
+        let sr_settings = SrSettings::new(String::from("http://127.0.0.1:1234"));
+        let decoder = AvroDecoder::new(sr_settings);
+        let result = decoder.decode(Some(&[0, 0, 0, 0])).await.unwrap_err();
+
+        assert_eq!(
+            result,
+            SRCError::non_retryable_without_cause("Invalid bytes [0, 0, 0, 0]")
+        )
+    }
+
+    #[tokio::test]
+    async fn test_decoder_wrong_data() {
+        let mut server = mockito::Server::new();
+        let _m = server.mock("GET", "/schemas/ids/1?deleted=true")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"schema":"{\"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
+            .create();
+
+        let sr_settings = SrSettings::new(server.url());
+        let decoder = AvroDecoder::new(sr_settings);
+        let error = decoder.decode(Some(&[0, 0, 0, 0, 1])).await.unwrap_err();
+
+        assert_eq!(error.error, "Could not transform bytes using schema")
+    }
+
+    #[tokio::test]
+    async fn test_decoder_with_name_wrong_data() {
+        let mut server = mockito::Server::new();
+        let _m = server.mock("GET", "/schemas/ids/1?deleted=true")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"schema":"{\"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
+            .create();
+
+        let sr_settings = SrSettings::new(server.url());
+        let decoder = AvroDecoder::new(sr_settings);
+        let error = decoder.decode(Some(&[0, 0, 0, 0, 1])).await.unwrap_err();
+
+        assert_eq!(error.error, "Could not transform bytes using schema")
+    }
+
+    #[tokio::test]
+    async fn test_decoder_no_json_response() {
+        let mut server = mockito::Server::new();
+        let _m = server.mock("GET", "/schemas/ids/1?deleted=true")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
+            .create();
+
+        let sr_settings = SrSettings::new(server.url());
+        let decoder = AvroDecoder::new(sr_settings);
+        let heartbeat = decoder.decode(Some(&[0, 0, 0, 0, 1, 6])).await.unwrap_err();
 
-        let heartbeat = decoder.decode(Some(&bytes)).await.unwrap().value;
+        assert_eq!(
+            heartbeat,
+            SRCError::new(
+                "could not parse to RawRegisteredSchema, schema might not exist on this schema registry, the http call failed, cause will give more information",
+                Some(String::from(
+                    "error decoding response body: expected `:` at line 1 column 130"
+                )),
+                false,
+            )
+            .into_cache()
+        )
+    }
+
+    #[tokio::test]
+    async fn test_decoder_with_name_no_json_response() {
+        let mut server = mockito::Server::new();
+        let _m = server.mock("GET", "/schemas/ids/1?deleted=true")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
+            .create();
+
+        let sr_settings = SrSettings::new(server.url());
+        let decoder = AvroDecoder::new(sr_settings);
+        let heartbeat = decoder.decode(Some(&[0, 0, 0, 0, 1, 6])).await.unwrap_err();
+
+        assert_eq!(
+            heartbeat,
+            SRCError::new(
+                "could not parse to RawRegisteredSchema, schema might not exist on this schema registry, the http call failed, cause will give more information",
+                Some(String::from(
+                    "error decoding response body: expected `:` at line 1 column 130"
+                )),
+                false,
+            )
+            .into_cache()
+        )
+    }
+
+    #[tokio::test]
+    async fn test_decoder_schema_registry_unavailable() {
+        let sr_settings = SrSettings::new(String::from("http://bogus"));
+        let decoder = AvroDecoder::new(sr_settings);
+        let err = decoder
+            .decode(Some(&[0, 0, 0, 10, 1, 6]))
+            .await
+            .unwrap_err();
+        assert_eq!(err.error, "http call to schema registry failed")
+    }
+
+    #[tokio::test]
+    async fn test_decoder_default_no_schema_in_response() {
+        let mut server = mockito::Server::new();
+        let _m = server.mock("GET", "/schemas/ids/1?deleted=true")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"no-schema":"{\"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
+            .create();
+
+        let sr_settings = SrSettings::new(server.url());
+        let decoder = AvroDecoder::new(sr_settings);
+        let err = decoder.decode(Some(&[0, 0, 0, 0, 1, 6])).await.unwrap_err();
+
+        assert_eq!(
+            err,
+            SRCError::new("Could not get raw schema from response", None, false).into_cache()
+        )
+    }
+
+    #[tokio::test]
+    async fn test_decoder_default_wrong_schema_in_response() {
+        let mut server = mockito::Server::new();
+        let _m = server.mock("GET", "/schemas/ids/1?deleted=true")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"schema":"{\"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\"}"}"#)
+            .create();
+
+        let sr_settings = SrSettings::new(server.url());
+        let decoder = AvroDecoder::new(sr_settings);
+        let err = decoder.decode(Some(&[0, 0, 0, 0, 1, 6])).await.unwrap_err();
+
+        assert_eq!(
+            err.error,
+           "Supplied raw value \"{\\\"type\\\":\\\"record\\\",\\\"name\\\":\\\"Heartbeat\\\",\\\"namespace\\\":\\\"nl.openweb.data\\\"}\" cant be turned into a Schema",
+        )
+    }
+
+    #[tokio::test]
+    async fn test_decoder_fixed_with_enum() {
+        let mut server = mockito::Server::new();
+        let _m = server.mock("GET", "/schemas/ids/6?deleted=true")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"schema":"{\"type\":\"record\",\"name\":\"ConfirmAccountCreation\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"id\",\"type\":{\"type\":\"fixed\",\"name\":\"Uuid\",\"size\":16}},{\"name\":\"a_type\",\"type\":{\"type\":\"enum\",\"name\":\"Atype\",\"symbols\":[\"AUTO\",\"MANUAL\"]}}]}"}"#)
+            .create();
+
+        let sr_settings = SrSettings::new(server.url());
+        let decoder = AvroDecoder::new(sr_settings);
+        let cac = decoder
+            .decode(Some(&[
+                0, 0, 0, 0, 6, 204, 240, 237, 74, 227, 188, 75, 46, 183, 163, 122, 214, 178, 72,
+                118, 162, 2,
+            ]))
+            .await
+            .unwrap()
+            .value;
+
+        assert_eq!(
+            cac,
+            Value::Record(vec![
+                (
+                    "id".to_string(),
+                    Value::Fixed(
+                        16,
+                        vec![
+                            204, 240, 237, 74, 227, 188, 75, 46, 183, 163, 122, 214, 178, 72, 118,
+                            162
+                        ],
+                    )
+                ),
+                ("a_type".to_string(), Value::Enum(1, "MANUAL".to_string()))
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_decoder_cache() {
+        let mut server = mockito::Server::new();
+        let sr_settings = SrSettings::new(server.url());
+        let decoder = AvroDecoder::new(sr_settings);
+        let bytes = [0, 0, 0, 0, 2, 6];
+
+        let _m = server
+            .mock("GET", "/schemas/ids/2?deleted=true")
+            .with_status(404)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"error_code":40403,"message":"Schema not found"}"#)
+            .create();
+        let err = decoder.decode(Some(&bytes)).await.unwrap_err();
+        assert_eq!(
+            err,
+            SRCError::not_found(
+                "schema registry responded with status 404 Not Found",
+                Some(String::from(
+                    r#"{"error_code":40403,"message":"Schema not found"}"#
+                ))
+            )
+            .into_cache()
+        );
+        let _m = server.mock("GET", "/schemas/ids/2?deleted=true")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"schema":"{\"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
+            .create();
+
+        let err = decoder.decode(Some(&bytes)).await.unwrap_err();
+        assert_eq!(
+            err,
+            SRCError::not_found(
+                "schema registry responded with status 404 Not Found",
+                Some(String::from(
+                    r#"{"error_code":40403,"message":"Schema not found"}"#
+                ))
+            )
+            .into_cache()
+        );
+
+        decoder.remove_errors_from_cache();
+
+        let heartbeat = decoder.decode(Some(&bytes)).await.unwrap().value;
         assert_eq!(
             heartbeat,
             Value::Record(vec![("beat".to_string(), Value::Long(3))])
@@ -1001,7 +2201,7 @@ mod tests {
         let sr_settings = SrSettings::new(String::from("http://127.0.0.1:1234"));
         let encoder = AvroEncoder::new(sr_settings);
         assert_eq!(
-            "AvroEncoder { sr_settings: SrSettings { urls: [\"http://127.0.0.1:1234\"], client: Client { accepts: Accepts, proxies: [Proxy(System({}), None)], referer: true, default_headers: {\"accept\": \"*/*\"} }, authorization: None }, direct_cache: {}, cache: {} }"
+            "AvroEncoder { sr_settings: SrSettings { urls: [\"http://127.0.0.1:1234\"], authorization: None }, direct_cache: {}, cache: {}, version_direct_cache: {}, version_cache: {}, reference_cache: {}, auto_register: false, id_encoding: ConfluentU32 }"
                 .to_owned(),
             format!("{:?}", encoder)
         )
@@ -1219,7 +2419,13 @@ mod tests {
             .unwrap_err();
         assert_eq!(
             err,
-            SRCError::new("Could not get id from response", None, false).into_cache()
+            SRCError::not_found(
+                "schema registry responded with status 404 Not Found",
+                Some(String::from(
+                    r#"{"error_code":40403,"message":"Schema not found"}"#
+                ))
+            )
+            .into_cache()
         );
 
         let _m = server.mock("GET", "/subjects/nl.openweb.data.Heartbeat/versions/latest")
@@ -1234,7 +2440,13 @@ mod tests {
             .unwrap_err();
         assert_eq!(
             err,
-            SRCError::non_retryable_without_cause("Could not get id from response").into_cache()
+            SRCError::not_found(
+                "schema registry responded with status 404 Not Found",
+                Some(String::from(
+                    r#"{"error_code":40403,"message":"Schema not found"}"#
+                ))
+            )
+            .into_cache()
         );
 
         encoder.remove_errors_from_cache();
@@ -1419,86 +2631,504 @@ mod tests {
         let error = encoder
             .encode(vec![("beat", Value::Long(3))], strategy)
             .await
-            .unwrap_err();
+            .unwrap_err();
+        assert_eq!(
+            error,
+            SRCError::new(
+                "schema registry responded with status 501 Not Implemented",
+                None,
+                true,
+            )
+            .into_cache()
+        )
+    }
+
+    #[tokio::test]
+    async fn error_when_invalid_schema() {
+        let registered_schema = RegisteredSchema {
+            id: 0,
+            schema_type: SchemaType::Avro,
+            schema: String::from(r#"{"type":"record","name":"Name"}"#),
+            references: vec![],
+        };
+        let sr_settings = SrSettings::new(String::from("http://127.0.0.1:1234"));
+        let result = to_avro_schema(&sr_settings, &DashMap::new(), registered_schema)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            result.error,
+            "Supplied raw value \"{\\\"type\\\":\\\"record\\\",\\\"name\\\":\\\"Name\\\"}\" cant be turned into a Schema"
+        )
+    }
+
+    #[tokio::test]
+    async fn error_when_invalid_type() {
+        let registered_schema = RegisteredSchema {
+            id: 0,
+            schema_type: SchemaType::Protobuf,
+            schema: String::from(
+                r#"syntax = "proto3"; package org.schema_registry_test_app.proto; message Result { string up = 1; string down = 2; }"#,
+            ),
+            references: vec![],
+        };
+        let sr_settings = SrSettings::new(String::from("http://127.0.0.1:1234"));
+        let err = to_avro_schema(&sr_settings, &DashMap::new(), registered_schema)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            SRCError::new("type Protobuf, is not supported", None, false)
+        )
+    }
+
+    #[tokio::test]
+    async fn test_primitive_schema() {
+        let mut server = mockito::Server::new();
+        let sr_settings = SrSettings::new(server.url());
+        let encoder = AvroEncoder::new(sr_settings);
+
+        let _m = server
+            .mock("POST", "/subjects/heartbeat-key/versions")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"id":4}"#)
+            .create();
+
+        let primitive_schema_strategy = SubjectNameStrategy::TopicNameStrategyWithSchema(
+            String::from("heartbeat"),
+            true,
+            get_supplied_schema(&Schema::String),
+        );
+        let bytes = encoder
+            .encode_struct("key-value", &primitive_schema_strategy)
+            .await;
+
+        assert_eq!(
+            bytes,
+            Ok(vec![
+                0, 0, 0, 0, 4, 18, 107, 101, 121, 45, 118, 97, 108, 117, 101
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_encode_struct_with_version() {
+        let mut server = mockito::Server::new_async().await;
+        let _m = server.mock("GET", "/subjects/heartbeat-value/versions/2")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"subject":"heartbeat-value","version":2,"id":5,"schema":"{\"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
+            .expect(1)
+            .create();
+
+        let sr_settings = SrSettings::new(server.url());
+        let encoder = AvroEncoder::new(sr_settings);
+        let strategy = SubjectNameStrategy::TopicNameStrategy(String::from("heartbeat"), false);
+        let bytes = encoder
+            .encode_struct_with_version(Heartbeat { beat: 3 }, &strategy, 2)
+            .await;
+        let again = encoder
+            .encode_with_version(vec![("beat", Value::Long(3))], strategy, 2)
+            .await;
+
+        assert_eq!(bytes, Ok(vec![0, 0, 0, 0, 5, 6]));
+        assert_eq!(bytes, again);
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn test_get_parsed_schema() {
+        let mut server = mockito::Server::new_async().await;
+        let _m = server.mock("GET", "/subjects/avro-test-value/versions/2")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"subject":"avro-test-value","version":2,"id":5,"schema":"{\"type\":\"record\",\"name\":\"AvroTest\",\"namespace\":\"org.schema_registry_test_app.avro\",\"fields\":[{\"name\":\"results\",\"type\":{\"type\":\"array\",\"items\":\"Result\"}}]}","references":[{"name":"org.schema_registry_test_app.avro.Result","subject":"avro-result","version":1}]}"#)
+            .create();
+        let _n = server.mock("GET", "/subjects/avro-result/versions/1")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"subject":"avro-result","version":1,"id":2,"schema":"{\"type\":\"record\",\"name\":\"Result\",\"namespace\":\"org.schema_registry_test_app.avro\",\"fields\":[{\"name\":\"up\",\"type\":\"string\"},{\"name\":\"down\",\"type\":\"string\"}]}"}"#)
+            .create();
+
+        let sr_settings = SrSettings::new(server.url());
+        let encoder = AvroEncoder::new(sr_settings);
+        let schema = encoder
+            .get_parsed_schema("avro-test-value", Some(2))
+            .await
+            .unwrap();
+
+        assert_eq!(schema.id, 5);
+        let name = get_name(&schema.parsed).unwrap();
+        assert_eq!(
+            name.fullname(None),
+            "org.schema_registry_test_app.avro.AvroTest"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_encode_struct_full() {
+        let mut server = mockito::Server::new_async().await;
+        let _m = server.mock("GET", "/subjects/heartbeat-nl.openweb.data.Heartbeat/versions/latest")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"subject":"heartbeat-nl.openweb.data.Heartbeat","version":1,"id":3,"schema":"{\"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
+            .create();
+
+        let sr_settings = SrSettings::new(server.url());
+        let encoder = AvroEncoder::new(sr_settings);
+        let strategy = SubjectNameStrategy::TopicRecordNameStrategy(
+            String::from("heartbeat"),
+            String::from("nl.openweb.data.Heartbeat"),
+        );
+        let result = encoder
+            .encode_struct_full(Heartbeat { beat: 3 }, &strategy)
+            .await
+            .unwrap();
+
+        assert_eq!(result.subject, strategy.get_subject().unwrap());
+        assert_eq!(result.subject, "heartbeat-nl.openweb.data.Heartbeat");
+        assert_eq!(result.id, 3);
+        assert_eq!(result.bytes, vec![0, 0, 0, 0, 3, 6]);
+    }
+
+    struct VersionedNamer;
+
+    impl SubjectNamer for VersionedNamer {
+        fn key_subject(&self, topic: &str, record: &str) -> String {
+            format!("{}.{}.key.v1", topic, record)
+        }
+
+        fn value_subject(&self, topic: &str, record: &str) -> String {
+            format!("{}.{}.v1", topic, record)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_encode_and_decode_with_apicurio_id() {
+        let mut server = mockito::Server::new_async().await;
+        let _m = server.mock("GET", "/subjects/heartbeat-value/versions/latest")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"subject":"heartbeat-value","version":1,"id":3,"schema":"{\"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
+            .create();
+        let _n = server.mock("GET", "/schemas/ids/3?deleted=true")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"schema":"{\"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
+            .create();
+
+        let mut encoder = AvroEncoder::new(SrSettings::new(server.url()));
+        encoder.set_id_encoding(IdEncoding::ApicurioU64);
+        let strategy = SubjectNameStrategy::TopicNameStrategy(String::from("heartbeat"), false);
+        let bytes = encoder
+            .encode_struct(Heartbeat { beat: 3 }, &strategy)
+            .await
+            .unwrap();
+        assert_eq!(bytes, vec![0, 0, 0, 0, 0, 0, 0, 0, 3, 6]);
+
+        let mut decoder = AvroDecoder::new(SrSettings::new(server.url()));
+        decoder.set_id_encoding(IdEncoding::ApicurioU64);
+        let heartbeat = decoder.decode(Some(&bytes)).await.unwrap().value;
+        assert_eq!(
+            heartbeat,
+            Value::Record(vec![("beat".to_string(), Value::Long(3))])
+        );
+    }
+
+    #[derive(serde::Serialize)]
+    struct RenamedHeartbeat {
+        pulse: i64,
+    }
+
+    impl apache_avro::AvroSchema for RenamedHeartbeat {
+        fn get_schema() -> Schema {
+            Schema::parse_str(r#"{"type":"record","name":"Heartbeat","namespace":"nl.openweb.data","fields":[{"name":"pulse","type":"long"}]}"#).unwrap()
+        }
+    }
+
+    #[derive(serde::Serialize)]
+    struct VerifiedHeartbeat {
+        beat: i64,
+    }
+
+    impl apache_avro::AvroSchema for VerifiedHeartbeat {
+        fn get_schema() -> Schema {
+            Schema::parse_str(r#"{"type":"record","name":"Heartbeat","namespace":"nl.openweb.data","fields":[{"name":"beat","type":"long"}]}"#).unwrap()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_encode_struct_verified() {
+        let mut server = mockito::Server::new_async().await;
+        let _m = server.mock("GET", "/subjects/heartbeat-value/versions/latest")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"subject":"heartbeat-value","version":1,"id":3,"schema":"{\"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
+            .create();
+
+        let sr_settings = SrSettings::new(server.url());
+        let encoder = AvroEncoder::new(sr_settings);
+        let strategy = SubjectNameStrategy::TopicNameStrategy(String::from("heartbeat"), false);
+
+        let bytes = encoder
+            .encode_struct_verified(VerifiedHeartbeat { beat: 3 }, &strategy)
+            .await
+            .unwrap();
+        assert_eq!(bytes, vec![0, 0, 0, 0, 3, 6]);
+
+        let error = encoder
+            .encode_struct_verified(RenamedHeartbeat { pulse: 3 }, &strategy)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.error,
+            "the schema of the item is not compatible with the schema with id 3"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_encode_struct_with_fixed_schema() {
+        let server = mockito::Server::new_async().await;
+        let sr_settings = SrSettings::new(server.url());
+        let encoder = AvroEncoder::new(sr_settings);
+        encoder
+            .add_fixed_schema(
+                "heartbeat-value",
+                3,
+                r#"{"type":"record","name":"Heartbeat","namespace":"nl.openweb.data","fields":[{"name":"beat","type":"long"}]}"#,
+            )
+            .unwrap();
+
+        let strategy = SubjectNameStrategy::TopicNameStrategy(String::from("heartbeat"), false);
+        let bytes = encoder
+            .encode_struct(Heartbeat { beat: 3 }, &strategy)
+            .await
+            .unwrap();
+
+        assert_eq!(bytes, vec![0, 0, 0, 0, 3, 6]);
+    }
+
+    #[tokio::test]
+    async fn test_encode_struct_with_fixed_schemas_from_dir() {
+        let server = mockito::Server::new_async().await;
+        let dir = std::env::temp_dir().join(format!("async-avro-encoder-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("heartbeat-value.3.avsc"),
+            r#"{"type":"record","name":"Heartbeat","namespace":"nl.openweb.data","fields":[{"name":"beat","type":"long"}]}"#,
+        )
+        .unwrap();
+
+        let sr_settings = SrSettings::new(server.url());
+        let encoder = AvroEncoder::new(sr_settings);
+        let added = encoder.add_fixed_schemas_from_dir(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(added, Ok(1));
+
+        let strategy = SubjectNameStrategy::TopicNameStrategy(String::from("heartbeat"), false);
+        let bytes = encoder
+            .encode_struct(Heartbeat { beat: 3 }, &strategy)
+            .await
+            .unwrap();
+
+        assert_eq!(bytes, vec![0, 0, 0, 0, 3, 6]);
+    }
+
+    #[tokio::test]
+    async fn test_encode_struct_optional() {
+        let server = mockito::Server::new_async().await;
+        let sr_settings = SrSettings::new(server.url());
+        let encoder = AvroEncoder::new(sr_settings);
+        encoder
+            .add_fixed_schema(
+                "heartbeat-value",
+                3,
+                r#"{"type":"record","name":"Heartbeat","namespace":"nl.openweb.data","fields":[{"name":"beat","type":"long"}]}"#,
+            )
+            .unwrap();
+
+        let strategy = SubjectNameStrategy::TopicNameStrategy(String::from("heartbeat"), false);
+        let bytes = encoder
+            .encode_struct_optional(Some(Heartbeat { beat: 3 }), &strategy)
+            .await
+            .unwrap();
+        assert_eq!(bytes, vec![0, 0, 0, 0, 3, 6]);
+
+        let bytes = encoder
+            .encode_struct_optional(None::<Heartbeat>, &strategy)
+            .await
+            .unwrap();
+        assert!(bytes.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_encode_struct_with_namer() {
+        let mut server = mockito::Server::new_async().await;
+        let _m = server.mock("GET", "/subjects/heartbeat.nl.openweb.data.Heartbeat.v1/versions/latest")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"subject":"heartbeat.nl.openweb.data.Heartbeat.v1","version":1,"id":3,"schema":"{\"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
+            .create();
+
+        let sr_settings = SrSettings::new(server.url());
+        let encoder = AvroEncoder::new(sr_settings);
+        let bytes = encoder
+            .encode_struct_with_namer(
+                Heartbeat { beat: 3 },
+                &VersionedNamer,
+                "heartbeat",
+                "nl.openweb.data.Heartbeat",
+                false,
+            )
+            .await;
+
+        assert_eq!(bytes, Ok(vec![0, 0, 0, 0, 3, 6]));
+    }
+
+    #[tokio::test]
+    async fn test_encode_struct_into() {
+        let mut server = mockito::Server::new_async().await;
+        let _m = server.mock("GET", "/subjects/heartbeat-value/versions/latest")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"subject":"heartbeat-value","version":1,"id":3,"schema":"{\"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
+            .create();
+
+        let sr_settings = SrSettings::new(server.url());
+        let encoder = AvroEncoder::new(sr_settings);
+        let strategy = SubjectNameStrategy::TopicNameStrategy(String::from("heartbeat"), false);
+        let mut buffer = Vec::new();
+        encoder
+            .encode_struct_into(Heartbeat { beat: 3 }, &strategy, &mut buffer)
+            .await
+            .unwrap();
+        assert_eq!(buffer, vec![0, 0, 0, 0, 3, 6]);
+
+        buffer.clear();
+        encoder
+            .encode_struct_into(Heartbeat { beat: 4 }, &strategy, &mut buffer)
+            .await
+            .unwrap();
         assert_eq!(
-            error,
-            SRCError::new(
-                "could not parse to RawRegisteredSchema, schema might not exist on this schema registry, the http call failed, cause will give more information",
-                Some(String::from(
-                    "error decoding response body: EOF while parsing a value at line 1 column 0"
-                )),
-                false,
-            )
-            .into_cache()
-        )
+            buffer,
+            encoder
+                .encode_struct(Heartbeat { beat: 4 }, &strategy)
+                .await
+                .unwrap()
+        );
     }
 
     #[tokio::test]
-    async fn error_when_invalid_schema() {
-        let registered_schema = RegisteredSchema {
-            id: 0,
-            schema_type: SchemaType::Avro,
-            schema: String::from(r#"{"type":"record","name":"Name"}"#),
-            references: vec![],
-        };
-        let sr_settings = SrSettings::new(String::from("http://127.0.0.1:1234"));
-        let result = to_avro_schema(&sr_settings, registered_schema)
+    async fn test_encode_struct_to_writer() {
+        let mut server = mockito::Server::new_async().await;
+        let _m = server.mock("GET", "/subjects/heartbeat-value/versions/latest")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"subject":"heartbeat-value","version":1,"id":3,"schema":"{\"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
+            .create();
+
+        let sr_settings = SrSettings::new(server.url());
+        let encoder = AvroEncoder::new(sr_settings);
+        let strategy = SubjectNameStrategy::TopicNameStrategy(String::from("heartbeat"), false);
+        let mut writer = futures::io::Cursor::new(Vec::new());
+        encoder
+            .encode_struct_to_writer(Heartbeat { beat: 3 }, &strategy, &mut writer)
             .await
-            .unwrap_err();
+            .unwrap();
+
         assert_eq!(
-            result.error,
-            "Supplied raw value \"{\\\"type\\\":\\\"record\\\",\\\"name\\\":\\\"Name\\\"}\" cant be turned into a Schema"
-        )
+            writer.into_inner(),
+            encoder
+                .encode_struct(Heartbeat { beat: 3 }, &strategy)
+                .await
+                .unwrap()
+        );
     }
 
     #[tokio::test]
-    async fn error_when_invalid_type() {
-        let registered_schema = RegisteredSchema {
-            id: 0,
-            schema_type: SchemaType::Protobuf,
-            schema: String::from(
-                r#"syntax = "proto3"; package org.schema_registry_test_app.proto; message Result { string up = 1; string down = 2; }"#,
-            ),
-            references: vec![],
-        };
-        let sr_settings = SrSettings::new(String::from("http://127.0.0.1:1234"));
-        let err = to_avro_schema(&sr_settings, registered_schema)
+    async fn test_encode_struct_single_object() {
+        let mut server = mockito::Server::new_async().await;
+        let _m = server.mock("GET", "/subjects/heartbeat-value/versions/latest")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"subject":"heartbeat-value","version":1,"id":3,"schema":"{\"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
+            .create();
+
+        let sr_settings = SrSettings::new(server.url());
+        let encoder = AvroEncoder::new(sr_settings);
+        let strategy = SubjectNameStrategy::TopicNameStrategy(String::from("heartbeat"), false);
+        let bytes = encoder
+            .encode_struct_single_object(Heartbeat { beat: 3 }, &strategy)
             .await
-            .unwrap_err();
+            .unwrap();
+
+        let schema = Schema::parse_str(r#"{"type":"record","name":"Heartbeat","namespace":"nl.openweb.data","fields":[{"name":"beat","type":"long"}]}"#).unwrap();
+        assert_eq!(bytes[..2], [0xC3, 0x01]);
+        assert_eq!(bytes[2..10], get_fingerprint(&schema));
+        let decoder = SingleObjectDecoder::new();
+        decoder.add_schema(schema);
         assert_eq!(
-            err,
-            SRCError::new("type Protobuf, is not supported", None, false)
-        )
+            decoder.decode(Some(&bytes)).unwrap().value,
+            Value::Record(vec![("beat".to_string(), Value::Long(3))])
+        );
     }
 
     #[tokio::test]
-    async fn test_primitive_schema() {
-        let mut server = mockito::Server::new();
+    async fn test_encode_struct_single_object_with_apicurio_id() {
+        let mut server = mockito::Server::new_async().await;
+        let _m = server.mock("GET", "/subjects/heartbeat-value/versions/latest")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"subject":"heartbeat-value","version":1,"id":3,"schema":"{\"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
+            .create();
+
         let sr_settings = SrSettings::new(server.url());
-        let encoder = AvroEncoder::new(sr_settings);
+        let mut encoder = AvroEncoder::new(sr_settings);
+        encoder.set_id_encoding(IdEncoding::ApicurioU64);
+        let strategy = SubjectNameStrategy::TopicNameStrategy(String::from("heartbeat"), false);
+        let bytes = encoder
+            .encode_struct_single_object(Heartbeat { beat: 3 }, &strategy)
+            .await
+            .unwrap();
+
+        let schema = Schema::parse_str(r#"{"type":"record","name":"Heartbeat","namespace":"nl.openweb.data","fields":[{"name":"beat","type":"long"}]}"#).unwrap();
+        assert_eq!(bytes.len(), 11);
+        let decoder = SingleObjectDecoder::new();
+        decoder.add_schema(schema);
+        assert_eq!(
+            decoder.decode(Some(&bytes)).unwrap().value,
+            Value::Record(vec![("beat".to_string(), Value::Long(3))])
+        );
+    }
 
+    #[tokio::test]
+    async fn test_encode_with_version_not_found() {
+        let mut server = mockito::Server::new_async().await;
         let _m = server
-            .mock("POST", "/subjects/heartbeat-key/versions")
-            .with_status(200)
+            .mock("GET", "/subjects/heartbeat-value/versions/3")
+            .with_status(404)
             .with_header("content-type", "application/vnd.schemaregistry.v1+json")
-            .with_body(r#"{"id":4}"#)
+            .with_body(r#"{"error_code":40402,"message":"Version 3 not found."}"#)
             .create();
 
-        let primitive_schema_strategy = SubjectNameStrategy::TopicNameStrategyWithSchema(
-            String::from("heartbeat"),
-            true,
-            get_supplied_schema(&Schema::String),
-        );
-        let bytes = encoder
-            .encode_struct("key-value", &primitive_schema_strategy)
+        let sr_settings = SrSettings::new(server.url());
+        let encoder = AvroEncoder::new(sr_settings);
+        let strategy = SubjectNameStrategy::TopicNameStrategy(String::from("heartbeat"), false);
+        let result = encoder
+            .encode_struct_with_version(Heartbeat { beat: 3 }, &strategy, 3)
             .await;
 
         assert_eq!(
-            bytes,
-            Ok(vec![
-                0, 0, 0, 0, 4, 18, 107, 101, 121, 45, 118, 97, 108, 117, 101
-            ])
+            result,
+            Err(SRCError::not_found(
+                "schema registry responded with status 404 Not Found",
+                Some(String::from(
+                    r#"{"error_code":40402,"message":"Version 3 not found."}"#
+                ))
+            )
+            .into_cache())
         );
+        assert!(result.unwrap_err().is_not_found());
     }
 
     #[tokio::test]
@@ -1520,6 +3150,43 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn referred_schema_fetched_once() {
+        let mut server = mockito::Server::new_async().await;
+        let sr_settings = SrSettings::new(server.url());
+        let decoder = AvroDecoder::new(sr_settings);
+
+        let _m = server.mock("GET", "/schemas/ids/7?deleted=true")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"schema":"{\"type\":\"record\",\"name\":\"First\",\"namespace\":\"org.schema_registry_test_app.avro\",\"fields\":[{\"name\":\"result\",\"type\":\"Result\"}]}","references":[{"name":"org.schema_registry_test_app.avro.Result","subject":"avro-result","version":1}]}"#)
+            .create();
+        let _n = server.mock("GET", "/schemas/ids/8?deleted=true")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"schema":"{\"type\":\"record\",\"name\":\"Second\",\"namespace\":\"org.schema_registry_test_app.avro\",\"fields\":[{\"name\":\"result\",\"type\":\"Result\"}]}","references":[{"name":"org.schema_registry_test_app.avro.Result","subject":"avro-result","version":1}]}"#)
+            .create();
+        let child = server.mock("GET", "/subjects/avro-result/versions/1")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"subject":"avro-result","version":1,"id":2,"schema":"{\"type\":\"record\",\"name\":\"Result\",\"namespace\":\"org.schema_registry_test_app.avro\",\"fields\":[{\"name\":\"up\",\"type\":\"string\"},{\"name\":\"down\",\"type\":\"string\"}]}"}"#)
+            .expect(1)
+            .create();
+
+        let expected = Value::Record(vec![(
+            "result".to_string(),
+            Value::Record(vec![
+                ("up".to_string(), Value::String("a".to_string())),
+                ("down".to_string(), Value::String("b".to_string())),
+            ]),
+        )]);
+        let first = decoder.decode(Some(&[0, 0, 0, 0, 7, 2, 97, 2, 98])).await;
+        assert_eq!(first.unwrap().value, expected);
+        let second = decoder.decode(Some(&[0, 0, 0, 0, 8, 2, 97, 2, 98])).await;
+        assert_eq!(second.unwrap().value, expected);
+        child.assert();
+    }
+
     #[tokio::test]
     async fn replace_referred_schema() {
         let mut server = mockito::Server::new();
@@ -1566,4 +3233,235 @@ mod tests {
         };
         assert_eq!(&1i64, counter_value, "counter is 1");
     }
+
+    fn heartbeat_local_schema() -> SuppliedSchema {
+        SuppliedSchema {
+            name: Some(String::from("nl.openweb.data.Heartbeat")),
+            schema_type: SchemaType::Avro,
+            schema: String::from(
+                r#"{"type":"record","name":"Heartbeat","namespace":"nl.openweb.data","fields":[{"name":"beat","type":"long"}]}"#,
+            ),
+            references: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_encode_struct_auto_register_not_enabled() {
+        let server = mockito::Server::new_async().await;
+        let sr_settings = SrSettings::new(server.url());
+        let encoder = AvroEncoder::new(sr_settings);
+        let strategy = SubjectNameStrategy::TopicNameStrategy(String::from("heartbeat"), false);
+
+        let result = encoder
+            .encode_struct_auto_register(Heartbeat { beat: 3 }, &strategy, heartbeat_local_schema())
+            .await;
+
+        assert_eq!(
+            result,
+            Err(SRCError::non_retryable_without_cause(
+                "Auto registering schemas is not enabled, use set_auto_register to enable it"
+            ))
+        )
+    }
+
+    #[tokio::test]
+    async fn test_encode_struct_auto_register_already_registered() {
+        let mut server = mockito::Server::new_async().await;
+        let _m = server.mock("GET", "/subjects/heartbeat-value/versions/latest")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"subject":"heartbeat-value","version":1,"id":3,"schema":"{\"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
+            .create();
+        let _p = server
+            .mock("POST", "/subjects/heartbeat-value/versions")
+            .expect(0)
+            .create();
+
+        let sr_settings = SrSettings::new(server.url());
+        let mut encoder = AvroEncoder::new(sr_settings);
+        encoder.set_auto_register(true);
+        let strategy = SubjectNameStrategy::TopicNameStrategy(String::from("heartbeat"), false);
+
+        let bytes = encoder
+            .encode_struct_auto_register(Heartbeat { beat: 3 }, &strategy, heartbeat_local_schema())
+            .await;
+
+        assert_eq!(bytes, Ok(vec![0, 0, 0, 0, 3, 6]));
+        _p.assert();
+    }
+
+    #[tokio::test]
+    async fn test_encode_struct_auto_register_registers_missing_subject() {
+        let mut server = mockito::Server::new_async().await;
+        let _m = server
+            .mock("GET", "/subjects/heartbeat-value/versions/latest")
+            .with_status(404)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"error_code":40401,"message":"Subject not found"}"#)
+            .create();
+        let _p = server
+            .mock("POST", "/subjects/heartbeat-value/versions")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"id":7}"#)
+            .expect(1)
+            .create();
+
+        let sr_settings = SrSettings::new(server.url());
+        let mut encoder = AvroEncoder::new(sr_settings);
+        encoder.set_auto_register(true);
+        let strategy = SubjectNameStrategy::TopicNameStrategy(String::from("heartbeat"), false);
+
+        let bytes = encoder
+            .encode_struct_auto_register(Heartbeat { beat: 3 }, &strategy, heartbeat_local_schema())
+            .await;
+        assert_eq!(bytes, Ok(vec![0, 0, 0, 0, 7, 6]));
+
+        let bytes = encoder
+            .encode_struct_auto_register(Heartbeat { beat: 4 }, &strategy, heartbeat_local_schema())
+            .await;
+        assert_eq!(bytes, Ok(vec![0, 0, 0, 0, 7, 8]));
+        _p.assert();
+    }
+
+    #[tokio::test]
+    async fn test_encode_struct_auto_register_other_error_not_registered() {
+        let mut server = mockito::Server::new_async().await;
+        let _m = server
+            .mock("GET", "/subjects/heartbeat-value/versions/latest")
+            .with_status(401)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"error_code":40101,"message":"Unauthorized"}"#)
+            .create();
+        let _p = server
+            .mock("POST", "/subjects/heartbeat-value/versions")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"id":7}"#)
+            .expect(0)
+            .create();
+
+        let sr_settings = SrSettings::new(server.url());
+        let mut encoder = AvroEncoder::new(sr_settings);
+        encoder.set_auto_register(true);
+        let strategy = SubjectNameStrategy::TopicNameStrategy(String::from("heartbeat"), false);
+
+        let error = encoder
+            .encode_struct_auto_register(Heartbeat { beat: 3 }, &strategy, heartbeat_local_schema())
+            .await
+            .unwrap_err();
+        assert!(!error.is_not_found());
+        _p.assert();
+    }
+
+    #[tokio::test]
+    async fn transitive_references_resolved() {
+        let mut server = mockito::Server::new_async().await;
+        let sr_settings = SrSettings::new(server.url());
+        let decoder = AvroDecoder::new(sr_settings);
+        let _b = server.mock("GET", "/schemas/ids/9?deleted=true")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"schema":"{\"type\":\"record\",\"name\":\"Book\",\"namespace\":\"test\",\"fields\":[{\"name\":\"author\",\"type\":\"Author\"}]}","references":[{"name":"test.Author","subject":"author","version":1}]}"#)
+            .create();
+        let _a = server.mock("GET", "/subjects/author/versions/1")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"subject":"author","version":1,"id":10,"schema":"{\"type\":\"record\",\"name\":\"Author\",\"namespace\":\"test\",\"fields\":[{\"name\":\"address\",\"type\":\"Address\"}]}","references":[{"name":"test.Address","subject":"address","version":1}]}"#)
+            .create();
+        let _c = server.mock("GET", "/subjects/address/versions/1")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"subject":"address","version":1,"id":11,"schema":"{\"type\":\"record\",\"name\":\"Address\",\"namespace\":\"test\",\"fields\":[{\"name\":\"city\",\"type\":\"string\"}]}"}"#)
+            .create();
+
+        let result = decoder.decode(Some(&[0, 0, 0, 0, 9, 2, 97])).await.unwrap();
+        assert_eq!(
+            result.value,
+            Value::Record(vec![(
+                "author".to_string(),
+                Value::Record(vec![(
+                    "address".to_string(),
+                    Value::Record(vec![("city".to_string(), Value::String("a".to_string()))]),
+                )]),
+            )])
+        );
+    }
+
+    #[tokio::test]
+    async fn reference_cycle_fails() {
+        let mut server = mockito::Server::new_async().await;
+        let sr_settings = SrSettings::new(server.url());
+        let decoder = AvroDecoder::new(sr_settings);
+        let _b = server.mock("GET", "/schemas/ids/9?deleted=true")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"schema":"{\"type\":\"record\",\"name\":\"Book\",\"namespace\":\"test\",\"fields\":[{\"name\":\"author\",\"type\":\"Author\"}]}","references":[{"name":"test.Author","subject":"author","version":1}]}"#)
+            .create();
+        let _a = server.mock("GET", "/subjects/author/versions/1")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"subject":"author","version":1,"id":10,"schema":"{\"type\":\"record\",\"name\":\"Author\",\"namespace\":\"test\",\"fields\":[{\"name\":\"address\",\"type\":\"Address\"}]}","references":[{"name":"test.Address","subject":"address","version":1}]}"#)
+            .create();
+        let _c = server.mock("GET", "/subjects/address/versions/1")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"subject":"address","version":1,"id":11,"schema":"{\"type\":\"record\",\"name\":\"Address\",\"namespace\":\"test\",\"fields\":[{\"name\":\"city\",\"type\":\"string\"}]}","references":[{"name":"test.Author","subject":"author","version":1}]}"#)
+            .create();
+
+        let error = decoder
+            .decode(Some(&[0, 0, 0, 0, 9, 2, 97]))
+            .await
+            .unwrap_err();
+        assert!(error.error.contains("refers back to itself"));
+    }
+
+    #[tokio::test]
+    async fn shared_reference_defined_once() {
+        let mut server = mockito::Server::new_async().await;
+        let sr_settings = SrSettings::new(server.url());
+        let decoder = AvroDecoder::new(sr_settings);
+        let _o = server.mock("GET", "/schemas/ids/12?deleted=true")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"schema":"{\"type\":\"record\",\"name\":\"Order\",\"namespace\":\"test\",\"fields\":[{\"name\":\"b\",\"type\":\"Buyer\"},{\"name\":\"c\",\"type\":\"Seller\"}]}","references":[{"name":"test.Buyer","subject":"buyer","version":1},{"name":"test.Seller","subject":"seller","version":1}]}"#)
+            .create();
+        let _b = server.mock("GET", "/subjects/buyer/versions/1")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"subject":"buyer","version":1,"id":13,"schema":"{\"type\":\"record\",\"name\":\"Buyer\",\"namespace\":\"test\",\"fields\":[{\"name\":\"m\",\"type\":\"Money\"}]}","references":[{"name":"test.Money","subject":"money","version":1}]}"#)
+            .create();
+        let _s = server.mock("GET", "/subjects/seller/versions/1")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"subject":"seller","version":1,"id":14,"schema":"{\"type\":\"record\",\"name\":\"Seller\",\"namespace\":\"test\",\"fields\":[{\"name\":\"m\",\"type\":\"Money\"},{\"name\":\"n\",\"type\":\"Money\"}]}","references":[{"name":"test.Money","subject":"money","version":1}]}"#)
+            .create();
+        let _m = server.mock("GET", "/subjects/money/versions/1")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"subject":"money","version":1,"id":15,"schema":"{\"type\":\"record\",\"name\":\"Money\",\"namespace\":\"test\",\"fields\":[{\"name\":\"amount\",\"type\":\"long\"}]}"}"#)
+            .create();
+
+        let result = decoder
+            .decode(Some(&[0, 0, 0, 0, 12, 2, 4, 6]))
+            .await
+            .unwrap();
+        let money = |amount| Value::Record(vec![("amount".to_string(), Value::Long(amount))]);
+        assert_eq!(
+            result.value,
+            Value::Record(vec![
+                (
+                    "b".to_string(),
+                    Value::Record(vec![("m".to_string(), money(1))])
+                ),
+                (
+                    "c".to_string(),
+                    Value::Record(vec![
+                        ("m".to_string(), money(2)),
+                        ("n".to_string(), money(3))
+                    ])
+                ),
+            ])
+        );
+    }
 }
@@ -1,12 +1,16 @@
 use crate::async_impl::schema_registry::SrSettings;
-use crate::avro_common::{DecodeResult, DecodeResultWithSchema};
+use crate::avro_common::{
+    DecodeResult, DecodeResultWithRaw, DecodeResultWithSchema, DecodeTiming, EncodeResult,
+};
 use crate::error::SRCError;
-use crate::schema_registry_common::SubjectNameStrategy;
+use crate::schema_registry_common::{SubjectNameStrategy, SubjectNamer};
 use crate::{
     async_impl::avro::{AvroDecoder, AvroEncoder},
     avro_common::AvroSchema,
 };
 use apache_avro::types::Value;
+use apache_avro::Schema;
+use serde::de::DeserializeOwned;
 use serde::Serialize;
 use std::sync::Arc;
 
@@ -29,6 +33,53 @@ impl EasyAvroDecoder {
     ) -> Result<Option<DecodeResultWithSchema>, SRCError> {
         self.decoder.decode_with_schema(bytes).await
     }
+    pub async fn decode_with_reader_schema(
+        &self,
+        bytes: Option<&[u8]>,
+        reader_schema: &Schema,
+    ) -> Result<DecodeResult, SRCError> {
+        self.decoder
+            .decode_with_reader_schema(bytes, reader_schema)
+            .await
+    }
+    pub async fn decode_auto_framed(
+        &self,
+        bytes: Option<&[u8]>,
+        fallback_id: Option<u32>,
+    ) -> Result<Option<DecodeResult>, SRCError> {
+        self.decoder.decode_auto_framed(bytes, fallback_id).await
+    }
+    pub async fn decode_unframed(
+        &self,
+        id: u32,
+        bytes: Option<&[u8]>,
+    ) -> Result<Option<DecodeResult>, SRCError> {
+        self.decoder.decode_unframed(id, bytes).await
+    }
+    pub async fn decode_with_id(
+        &self,
+        bytes: Option<&[u8]>,
+    ) -> Result<Option<(u32, DecodeResult)>, SRCError> {
+        self.decoder.decode_with_id(bytes).await
+    }
+    pub async fn decode_with_raw(
+        &self,
+        bytes: Option<&[u8]>,
+    ) -> Result<Option<DecodeResultWithRaw>, SRCError> {
+        self.decoder.decode_with_raw(bytes).await
+    }
+    pub async fn decode_timed(
+        &self,
+        bytes: Option<&[u8]>,
+    ) -> Result<(DecodeResult, DecodeTiming), SRCError> {
+        self.decoder.decode_timed(bytes).await
+    }
+    pub async fn decode_struct<T: DeserializeOwned>(
+        &self,
+        bytes: Option<&[u8]>,
+    ) -> Result<Option<T>, SRCError> {
+        self.decoder.decode_struct(bytes).await
+    }
 }
 
 /// An encoder used to transform a [Value] to bytes, its much like [AvroEncoder] but wrapped with an arc to make it easier.
@@ -57,6 +108,53 @@ impl EasyAvroEncoder {
             .encode_struct(item, subject_name_strategy)
             .await
     }
+    pub async fn encode_struct_optional(
+        &self,
+        item: Option<impl Serialize>,
+        subject_name_strategy: &SubjectNameStrategy,
+    ) -> Result<Vec<u8>, SRCError> {
+        self.encoder
+            .encode_struct_optional(item, subject_name_strategy)
+            .await
+    }
+    pub async fn encode_struct_with_namer(
+        &self,
+        item: impl Serialize,
+        subject_namer: &dyn SubjectNamer,
+        topic: &str,
+        record: &str,
+        is_key: bool,
+    ) -> Result<Vec<u8>, SRCError> {
+        self.encoder
+            .encode_struct_with_namer(item, subject_namer, topic, record, is_key)
+            .await
+    }
+    pub async fn encode_struct_full(
+        &self,
+        item: impl Serialize,
+        subject_name_strategy: &SubjectNameStrategy,
+    ) -> Result<EncodeResult, SRCError> {
+        self.encoder
+            .encode_struct_full(item, subject_name_strategy)
+            .await
+    }
+    pub async fn encode_struct_with_version(
+        &self,
+        item: impl Serialize,
+        subject_name_strategy: &SubjectNameStrategy,
+        version: u32,
+    ) -> Result<Vec<u8>, SRCError> {
+        self.encoder
+            .encode_struct_with_version(item, subject_name_strategy, version)
+            .await
+    }
+    pub async fn get_parsed_schema(
+        &self,
+        subject: &str,
+        version: Option<u32>,
+    ) -> Result<Arc<AvroSchema>, SRCError> {
+        self.encoder.get_parsed_schema(subject, version).await
+    }
     pub async fn get_schema_and_id(
         &self,
         key: &str,
@@ -1,6 +1,7 @@
 use crate::async_impl::json::{DecodeResult, JsonDecoder, JsonEncoder};
 use crate::async_impl::schema_registry::SrSettings;
 use crate::error::SRCError;
+use crate::json_common::JsonSchemaDraft;
 use crate::schema_registry_common::SubjectNameStrategy;
 use serde_json::Value;
 use std::sync::Arc;
@@ -15,9 +16,22 @@ impl EasyJsonDecoder {
         let decoder = Arc::new(JsonDecoder::new(sr_settings));
         EasyJsonDecoder { decoder }
     }
+    pub fn new_with_draft(sr_settings: SrSettings, draft: JsonSchemaDraft) -> EasyJsonDecoder {
+        let decoder = Arc::new(JsonDecoder::new_with_draft(sr_settings, draft));
+        EasyJsonDecoder { decoder }
+    }
     pub async fn decode(&self, bytes: Option<&[u8]>) -> Result<Option<DecodeResult>, SRCError> {
         self.decoder.decode(bytes).await
     }
+    pub async fn decode_validate_against_subject(
+        &self,
+        bytes: Option<&[u8]>,
+        subject_name_strategy: &SubjectNameStrategy,
+    ) -> Result<Option<DecodeResult>, SRCError> {
+        self.decoder
+            .decode_validate_against_subject(bytes, subject_name_strategy)
+            .await
+    }
 }
 
 /// An encoder used to transform a [Value] to bytes, its much like [JsonEncoder] but wrapped with an arc to make it easier.
@@ -30,6 +44,10 @@ impl EasyJsonEncoder {
         let encoder = Arc::new(JsonEncoder::new(sr_settings));
         EasyJsonEncoder { encoder }
     }
+    pub fn new_with_draft(sr_settings: SrSettings, draft: JsonSchemaDraft) -> EasyJsonEncoder {
+        let encoder = Arc::new(JsonEncoder::new_with_draft(sr_settings, draft));
+        EasyJsonEncoder { encoder }
+    }
     pub async fn encode(
         &self,
         value: &Value,
@@ -44,6 +62,7 @@ mod tests {
     use crate::async_impl::easy_json::{EasyJsonDecoder, EasyJsonEncoder};
     use crate::async_impl::json::validate;
     use crate::async_impl::schema_registry::SrSettings;
+    use crate::json_common::JsonSchemaDraft;
     use crate::schema_registry_common::{get_payload, SubjectNameStrategy};
 
     use serde_json::Value;
@@ -117,4 +136,36 @@ mod tests {
 
         assert_eq!(encoded_data, json_result_java_bytes())
     }
+
+    #[tokio::test]
+    async fn test_decoder_with_draft() {
+        let mut server = mockito::Server::new_async().await;
+        let _m = server
+            .mock("GET", "/subjects/testresult-value/versions/latest")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(get_json_body(
+                r#"{\"type\":\"object\",\"properties\":{\"up\":{\"type\":\"string\"}},\"unevaluatedProperties\":false}"#,
+                10,
+            ))
+            .create();
+
+        let draft_7 = EasyJsonDecoder::new(SrSettings::new(server.url()));
+        let draft_2019_09 = EasyJsonDecoder::new_with_draft(
+            SrSettings::new(server.url()),
+            JsonSchemaDraft::Draft2019_09,
+        );
+        let strategy = SubjectNameStrategy::TopicNameStrategy(String::from("testresult"), false);
+        let mut bytes = vec![0, 0, 0, 0, 10];
+        bytes.extend_from_slice(br#"{"up":"STRING","down":"string"}"#);
+
+        assert!(draft_7
+            .decode_validate_against_subject(Some(&bytes), &strategy)
+            .await
+            .is_ok());
+        assert!(draft_2019_09
+            .decode_validate_against_subject(Some(&bytes), &strategy)
+            .await
+            .is_err());
+    }
 }
@@ -1,4 +1,4 @@
-use crate::async_impl::proto_decoder::{DecodeResultWithContext, ProtoDecoder};
+use crate::async_impl::proto_decoder::{DecodeContext, DecodeResultWithContext, ProtoDecoder};
 use crate::async_impl::schema_registry::SrSettings;
 use crate::error::SRCError;
 use protofish::decode::Value;
@@ -25,6 +25,9 @@ impl EasyProtoDecoder {
     ) -> Result<Option<DecodeResultWithContext>, SRCError> {
         self.decoder.decode_with_context(bytes).await
     }
+    pub async fn get_decode_context(&self, id: u32) -> Result<Arc<DecodeContext>, SRCError> {
+        self.decoder.get_decode_context(id).await
+    }
 }
 
 #[cfg(test)]
@@ -1,10 +1,10 @@
-use std::str::FromStr;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use dashmap::mapref::entry::Entry;
 use dashmap::DashMap;
 use futures::future::{BoxFuture, FutureExt, Shared};
 use futures::stream::{self, StreamExt};
+use log::warn;
 use serde_json::Value;
 use url::Url;
 use valico::json_schema::schema::ScopedSchema;
@@ -14,10 +14,12 @@ use crate::async_impl::schema_registry::{
     get_referenced_schema, get_schema_by_id_and_type, get_schema_by_subject, SrSettings,
 };
 use crate::error::SRCError;
-use crate::json_common::{fetch_fallback, fetch_id, handle_validation, to_bytes, to_value};
+use crate::json_common::{
+    fetch_fallback, fetch_id, handle_validation, reference_url, scope_for_draft, to_bytes,
+    to_value, JsonSchemaDraft,
+};
 use crate::schema_registry_common::{
-    get_bytes_result, BytesResult, RegisteredReference, RegisteredSchema, SchemaType,
-    SubjectNameStrategy,
+    get_bytes_result, BytesResult, RegisteredSchema, SchemaType, SubjectNameStrategy,
 };
 
 /// Encoder that works by prepending the correct bytes in order to make it valid schema registry
@@ -29,6 +31,9 @@ pub struct JsonEncoder<'a> {
     sr_settings: SrSettings,
     direct_cache: DashMap<String, Arc<JsonSchema>>,
     cache: DashMap<String, SharedFutureSchema<'a>>,
+    compiled_cache: DashMap<u32, Option<Arc<Mutex<CompiledJsonSchema>>>>,
+    draft: JsonSchemaDraft,
+    skip_validation_on_compile_error: bool,
 }
 
 type SharedFutureSchema<'a> = Shared<BoxFuture<'a, Result<Arc<JsonSchema>, SRCError>>>;
@@ -36,12 +41,25 @@ type SharedFutureSchema<'a> = Shared<BoxFuture<'a, Result<Arc<JsonSchema>, SRCEr
 impl<'a> JsonEncoder<'a> {
     /// Creates a new json encoder
     pub fn new(sr_settings: SrSettings) -> JsonEncoder<'a> {
+        JsonEncoder::new_with_draft(sr_settings, JsonSchemaDraft::default())
+    }
+    /// Creates a new json encoder, compiling the schemas using the supplied draft
+    pub fn new_with_draft(sr_settings: SrSettings, draft: JsonSchemaDraft) -> JsonEncoder<'a> {
         JsonEncoder {
             sr_settings,
             direct_cache: DashMap::new(),
             cache: DashMap::new(),
+            compiled_cache: DashMap::new(),
+            draft,
+            skip_validation_on_compile_error: false,
         }
     }
+    /// When set, values are encoded without validation for schemas that could not be compiled, for
+    /// example because they use a regex not supported by valico. A warning is logged once per
+    /// schema id.
+    pub fn set_skip_validation_on_compile_error(&mut self, skip: bool) {
+        self.skip_validation_on_compile_error = skip;
+    }
     /// Removes errors from the cache, can be usefull to retry failed encodings.
     pub fn remove_errors_from_cache(&self) {
         self.cache.retain(|_, v| match v.peek() {
@@ -57,10 +75,36 @@ impl<'a> JsonEncoder<'a> {
         subject_name_strategy: SubjectNameStrategy,
     ) -> Result<Vec<u8>, SRCError> {
         let key = subject_name_strategy.get_subject()?;
-        let schema = &*self.get_schema(key, subject_name_strategy).await?;
-        let id = schema.id;
-        validate(schema.clone(), value)?;
-        to_bytes(id, value)
+        let schema = self.get_schema(key, subject_name_strategy).await?;
+        if let Some(compiled) = self.get_compiled_schema(&schema)? {
+            compiled_validate(&compiled, value)?;
+        }
+        to_bytes(schema.id, value)
+    }
+
+    /// Gets the compiled schema for the id of the json schema, compiling the schema, including the
+    /// references, only once. Because the key is the id, a new id for the subject will cause a
+    /// new compilation. Returns none when compilation failed, and validation should be skipped.
+    fn get_compiled_schema(
+        &self,
+        schema: &JsonSchema,
+    ) -> Result<Option<Arc<Mutex<CompiledJsonSchema>>>, SRCError> {
+        if let Some(compiled) = self.compiled_cache.get(&schema.id) {
+            return Ok(compiled.value().clone());
+        }
+        let compiled = match compile(schema.clone(), self.draft) {
+            Ok(v) => Some(Arc::new(Mutex::new(v))),
+            Err(e) if self.skip_validation_on_compile_error => {
+                warn!(
+                    "could not compile json schema with id {}, values will not be validated: {}",
+                    schema.id, e
+                );
+                None
+            }
+            Err(e) => return Err(e),
+        };
+        self.compiled_cache.insert(schema.id, compiled.clone());
+        Ok(compiled)
     }
 
     async fn get_schema(
@@ -70,6 +114,7 @@ impl<'a> JsonEncoder<'a> {
     ) -> Result<Arc<JsonSchema>, SRCError> {
         match self.direct_cache.get(&key) {
             None => {
+                self.sr_settings.observe_cache_miss();
                 let result = self
                     .get_schema_by_shared_future(key.clone(), subject_name_strategy)
                     .await;
@@ -80,7 +125,10 @@ impl<'a> JsonEncoder<'a> {
                 };
                 result
             }
-            Some(result) => Ok(result.value().clone()),
+            Some(result) => {
+                self.sr_settings.observe_cache_hit();
+                Ok(result.value().clone())
+            }
         }
     }
 
@@ -123,8 +171,44 @@ fn add_refs_to_scope(scope: &mut Scope, schema: JsonSchema) -> Result<ScopedSche
     }
 }
 
+/// Json schema compiled into a scope, so it can be used for multiple validations.
+#[derive(Debug)]
+struct CompiledJsonSchema {
+    scope: Scope,
+    url: Url,
+}
+
+fn compile(schema: JsonSchema, draft: JsonSchemaDraft) -> Result<CompiledJsonSchema, SRCError> {
+    let mut scope = scope_for_draft(draft);
+    let url = schema.url.clone();
+    add_refs_to_scope(&mut scope, schema)?;
+    Ok(CompiledJsonSchema { scope, url })
+}
+
+fn compiled_validate(compiled: &Mutex<CompiledJsonSchema>, value: &Value) -> Result<(), SRCError> {
+    let compiled = match compiled.lock() {
+        Ok(v) => v,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    match compiled.scope.resolve(&compiled.url) {
+        Some(schema) => handle_validation(schema.validate(value), value),
+        None => Err(SRCError::non_retryable_without_cause(
+            "could not get schema from scope",
+        )),
+    }
+}
+
 pub fn validate(schema: JsonSchema, value: &Value) -> Result<(), SRCError> {
-    let mut scope = Scope::new();
+    validate_with_draft(schema, value, JsonSchemaDraft::default())
+}
+
+/// Validates the value against the schema, compiling the schema using the supplied draft.
+pub fn validate_with_draft(
+    schema: JsonSchema,
+    value: &Value,
+    draft: JsonSchemaDraft,
+) -> Result<(), SRCError> {
+    let mut scope = scope_for_draft(draft);
     let schema = add_refs_to_scope(&mut scope, schema)?;
     let validation = schema.validate(value);
     handle_validation(validation, value)
@@ -145,6 +229,9 @@ pub struct JsonDecoder<'a> {
     sr_settings: SrSettings,
     direct_cache: DashMap<u32, Arc<JsonSchema>>,
     cache: DashMap<u32, SharedFutureSchema<'a>>,
+    subject_cache: DashMap<String, Arc<JsonSchema>>,
+    compiled_cache: DashMap<u32, Arc<Mutex<CompiledJsonSchema>>>,
+    draft: JsonSchemaDraft,
 }
 
 impl<'a> JsonDecoder<'a> {
@@ -154,10 +241,17 @@ impl<'a> JsonDecoder<'a> {
     /// comes back as an error you can use remove_errors_from_cache to clean the cache, keeping the
     /// correctly fetched schema's
     pub fn new(sr_settings: SrSettings) -> JsonDecoder<'a> {
+        JsonDecoder::new_with_draft(sr_settings, JsonSchemaDraft::default())
+    }
+    /// Creates a new json decoder, compiling the schemas using the supplied draft
+    pub fn new_with_draft(sr_settings: SrSettings, draft: JsonSchemaDraft) -> JsonDecoder<'a> {
         JsonDecoder {
             sr_settings,
             direct_cache: DashMap::new(),
             cache: DashMap::new(),
+            subject_cache: DashMap::new(),
+            compiled_cache: DashMap::new(),
+            draft,
         }
     }
     /// Remove al the errors from the cache, you might need to/want to run this when a recoverable
@@ -180,6 +274,89 @@ impl<'a> JsonDecoder<'a> {
             ))),
         }
     }
+    /// Decodes bytes without magic byte and id, using the schema with the given id. This is useful
+    /// when the id is known out of band, for example for data stored without the framing. Returns
+    /// none when the bytes are none.
+    pub async fn decode_unframed(
+        &self,
+        id: u32,
+        bytes: Option<&[u8]>,
+    ) -> Result<Option<DecodeResult>, SRCError> {
+        match bytes {
+            None => Ok(None),
+            Some(bytes) => Ok(Some(self.deserialize(id, bytes).await?)),
+        }
+    }
+    /// Like decode, but validating the value against the latest schema of the subject, ignoring the
+    /// id in the bytes. This way the consumer enforces the schema it expects. The schema for the
+    /// subject is only fetched and compiled once, and used until clear_subject_cache is called.
+    pub async fn decode_validate_against_subject(
+        &self,
+        bytes: Option<&[u8]>,
+        subject_name_strategy: &SubjectNameStrategy,
+    ) -> Result<Option<DecodeResult>, SRCError> {
+        let bytes = match get_bytes_result(bytes) {
+            BytesResult::Null => return Ok(None),
+            BytesResult::Valid(_, bytes) => bytes,
+            BytesResult::Invalid(i) => {
+                return Err(SRCError::non_retryable_without_cause(&format!(
+                    "Invalid bytes: {:?}",
+                    i
+                )))
+            }
+        };
+        let schema = self.get_schema_for_subject(subject_name_strategy).await?;
+        let value = match serde_json::from_slice(&bytes) {
+            Ok(v) => v,
+            Err(e) => {
+                return Err(SRCError::non_retryable_with_cause(
+                    e,
+                    "could not create value from bytes",
+                ))
+            }
+        };
+        compiled_validate(&*self.get_compiled_schema(&schema)?, &value)?;
+        Ok(Some(DecodeResult {
+            schema: (*schema).clone(),
+            value,
+        }))
+    }
+
+    /// Removes the schemas fetched for the subjects by decode_validate_against_subject, so the next
+    /// call fetches the latest schema again.
+    pub fn clear_subject_cache(&self) {
+        self.subject_cache.clear();
+    }
+
+    async fn get_schema_for_subject(
+        &self,
+        subject_name_strategy: &SubjectNameStrategy,
+    ) -> Result<Arc<JsonSchema>, SRCError> {
+        let subject = subject_name_strategy.get_subject()?;
+        if let Some(schema) = self.subject_cache.get(&subject) {
+            return Ok(schema.value().clone());
+        }
+        let registered_schema =
+            get_schema_by_subject(&self.sr_settings, subject_name_strategy).await?;
+        let schema = Arc::new(to_json_schema(&self.sr_settings, None, registered_schema).await?);
+        self.subject_cache.insert(subject, schema.clone());
+        Ok(schema)
+    }
+
+    /// Gets the compiled schema for the id of the json schema, compiling the schema, including the
+    /// references, only once.
+    fn get_compiled_schema(
+        &self,
+        schema: &JsonSchema,
+    ) -> Result<Arc<Mutex<CompiledJsonSchema>>, SRCError> {
+        if let Some(compiled) = self.compiled_cache.get(&schema.id) {
+            return Ok(compiled.value().clone());
+        }
+        let compiled = Arc::new(Mutex::new(compile(schema.clone(), self.draft)?));
+        self.compiled_cache.insert(schema.id, compiled.clone());
+        Ok(compiled)
+    }
+
     /// The actual deserialization trying to get the id from the bytes to retrieve the schema, and
     /// using a reader transforms the bytes to a value.
     async fn deserialize(&self, id: u32, bytes: &[u8]) -> Result<DecodeResult, SRCError> {
@@ -199,6 +376,7 @@ impl<'a> JsonDecoder<'a> {
     async fn get_schema(&self, id: u32) -> Result<Arc<JsonSchema>, SRCError> {
         match self.direct_cache.get(&id) {
             None => {
+                self.sr_settings.observe_cache_miss();
                 let result = self.get_schema_by_shared_future(id).await;
                 if result.is_ok() && !self.direct_cache.contains_key(&id) {
                     self.direct_cache.insert(id, result.clone().unwrap());
@@ -206,7 +384,10 @@ impl<'a> JsonDecoder<'a> {
                 };
                 result
             }
-            Some(result) => Ok(result.value().clone()),
+            Some(result) => {
+                self.sr_settings.observe_cache_hit();
+                Ok(result.value().clone())
+            }
         }
     }
 
@@ -234,16 +415,9 @@ impl<'a> JsonDecoder<'a> {
     }
 }
 
-fn reference_url(rr: &RegisteredReference) -> Result<Url, SRCError> {
-    match Url::from_str(&rr.name) {
-        Ok(v) => Ok(v),
-        Err(e) => Err(SRCError::non_retryable_with_cause(e, &format!("reference schema with subject {} and version {} has invalid id {}, it has to be a fully qualified url", rr.subject, rr.version, rr.name)))
-    }
-}
-
-fn main_url(schema: &Value, sr_settings: &SrSettings, id: u32) -> Url {
+fn main_url(schema: &Value, sr_settings: &SrSettings, id: u32) -> Result<Url, SRCError> {
     match fetch_id(schema) {
-        Some(url) => url,
+        Some(url) => Ok(url),
         None => fetch_fallback(sr_settings.url(), id),
     }
 }
@@ -254,22 +428,25 @@ fn to_json_schema(
     registered_schema: RegisteredSchema,
 ) -> BoxFuture<Result<JsonSchema, SRCError>> {
     async move {
+        let schema: Value = to_value(&registered_schema.schema)?;
+        let url = match optional_url {
+            Some(v) => v,
+            None => main_url(&schema, sr_settings, registered_schema.id)?,
+        };
         let refs: Result<Vec<JsonSchema>, SRCError> = stream::iter(registered_schema.references)
-            .then(|rr| async move {
-                let url = reference_url(&rr)?;
-                let rs = get_referenced_schema(sr_settings, &rr).await?;
-                to_json_schema(sr_settings, Some(url), rs).await
+            .then(|rr| {
+                let base = &url;
+                async move {
+                    let ref_url = reference_url(base, &rr)?;
+                    let rs = get_referenced_schema(sr_settings, &rr).await?;
+                    to_json_schema(sr_settings, Some(ref_url), rs).await
+                }
             })
             .collect::<Vec<_>>()
             .await
             .into_iter()
             .collect();
         let references = refs?;
-        let schema: Value = to_value(&registered_schema.schema)?;
-        let url = match optional_url {
-            Some(v) => v,
-            None => main_url(&schema, sr_settings, registered_schema.id),
-        };
         Ok(JsonSchema {
             id: registered_schema.id,
             url,
@@ -291,12 +468,15 @@ pub struct DecodeResult {
 #[cfg(test)]
 mod tests {
     use std::fs::{read_to_string, File};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
 
     use serde_json::Value;
 
     use crate::async_impl::json::{validate, JsonDecoder, JsonEncoder};
     use crate::async_impl::schema_registry::SrSettings;
-    use crate::schema_registry_common::{get_payload, SubjectNameStrategy};
+    use crate::json_common::JsonSchemaDraft;
+    use crate::schema_registry_common::{get_payload, Observer, SubjectNameStrategy};
     use test_utils::{
         get_json_body, get_json_body_with_reference, json_get_result_references,
         json_incorrect_bytes, json_result_java_bytes, json_result_schema,
@@ -325,6 +505,60 @@ mod tests {
         assert_eq!(encoded_data, json_result_java_bytes())
     }
 
+    #[tokio::test]
+    async fn test_encode_compiles_schema_once() {
+        let mut server = mockito::Server::new_async().await;
+        let _m = server
+            .mock("GET", "/subjects/testresult-value/versions/latest")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(get_json_body(json_result_schema(), 10))
+            .create();
+
+        let sr_settings = SrSettings::new(server.url());
+        let encoder = JsonEncoder::new(sr_settings);
+        let strategy = SubjectNameStrategy::TopicNameStrategy(String::from("testresult"), false);
+        let result_example: Value =
+            serde_json::from_reader(File::open("tests/schema/result-example.json").unwrap())
+                .unwrap();
+
+        let first = encoder
+            .encode(&result_example, strategy.clone())
+            .await
+            .unwrap();
+        let compiled = encoder
+            .compiled_cache
+            .get(&10)
+            .unwrap()
+            .value()
+            .clone()
+            .unwrap();
+        let second = encoder.encode(&result_example, strategy).await.unwrap();
+
+        assert_eq!(first, second);
+        assert_eq!(1, encoder.compiled_cache.len());
+        assert!(Arc::ptr_eq(
+            &compiled,
+            encoder
+                .compiled_cache
+                .get(&10)
+                .unwrap()
+                .value()
+                .as_ref()
+                .unwrap()
+        ));
+        let error = encoder
+            .encode(
+                &Value::String(String::from("Foo")),
+                SubjectNameStrategy::TopicNameStrategy(String::from("testresult"), false),
+            )
+            .await
+            .unwrap_err();
+        assert!(error
+            .error
+            .contains("was not valid according to the schema"))
+    }
+
     #[tokio::test]
     async fn test_encode_schema_with_id() {
         let mut server = mockito::Server::new();
@@ -377,6 +611,32 @@ mod tests {
         assert!(encoded_data.is_ok());
     }
 
+    #[tokio::test]
+    async fn test_decode_unframed() {
+        let mut server = mockito::Server::new_async().await;
+        let result_value: String = read_to_string("tests/schema/result-example.json")
+            .unwrap()
+            .parse()
+            .unwrap();
+        let _m = server
+            .mock("GET", "/schemas/ids/7?deleted=true")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(get_json_body(json_result_schema(), 7))
+            .create();
+
+        let sr_settings = SrSettings::new(server.url());
+        let decoder = JsonDecoder::new(sr_settings);
+        let message = decoder
+            .decode_unframed(7, Some(result_value.as_bytes()))
+            .await
+            .unwrap()
+            .unwrap();
+        validate(message.schema, &message.value).unwrap();
+        assert_eq!("STRING", message.value.get("up").unwrap().as_str().unwrap());
+        assert!(decoder.decode_unframed(7, None).await.unwrap().is_none());
+    }
+
     #[tokio::test]
     async fn test_decoder_default() {
         let mut server = mockito::Server::new();
@@ -692,7 +952,7 @@ mod tests {
         let sr_settings = SrSettings::new(String::from("http://127.0.0.1:1234"));
         let encoder = JsonEncoder::new(sr_settings);
         println!("{:?}", encoder);
-        assert!(format!("{:?}", encoder).starts_with("JsonEncoder { sr_settings: SrSettings { urls: [\"http://127.0.0.1:1234\"], client: Client { accepts: Accepts"))
+        assert!(format!("{:?}", encoder).starts_with("JsonEncoder { sr_settings: SrSettings { urls: [\"http://127.0.0.1:1234\"], authorization: None }"))
     }
 
     #[test]
@@ -701,7 +961,7 @@ mod tests {
         let decoder = JsonDecoder::new(sr_settings);
         println!("{:?}", decoder);
         assert!(
-                   format!("{:?}", decoder).starts_with("JsonDecoder { sr_settings: SrSettings { urls: [\"http://127.0.0.1:1234\"], client: Client { accepts: Accepts")
+                   format!("{:?}", decoder).starts_with("JsonDecoder { sr_settings: SrSettings { urls: [\"http://127.0.0.1:1234\"], authorization: None }")
         )
     }
 
@@ -754,6 +1014,120 @@ mod tests {
             .ends_with("was not valid because of missing references"))
     }
 
+    #[tokio::test]
+    async fn test_encode_relative_reference() {
+        let mut server = mockito::Server::new_async().await;
+        let _m = server
+            .mock("GET", "/subjects/testresult-value/versions/latest")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(get_json_body_with_reference(
+                r#"{\"type\":\"object\",\"properties\":{\"result\":{\"$ref\":\"result.json\"}},\"required\":[\"result\"]}"#,
+                10,
+                r#"{"name": "result.json", "subject": "result.json", "version": 1}"#,
+            ))
+            .create();
+        let _m = server
+            .mock("GET", "/subjects/result.json/versions/1")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(get_json_body(json_result_schema(), 4))
+            .create();
+
+        let sr_settings = SrSettings::new(server.url());
+        let encoder = JsonEncoder::new(sr_settings);
+        let strategy = SubjectNameStrategy::TopicNameStrategy(String::from("testresult"), false);
+        let value: Value =
+            serde_json::from_str(r#"{"result":{"up":"STRING","down":"string"}}"#).unwrap();
+
+        let result = encoder.encode(&value, strategy).await.unwrap();
+
+        assert_eq!(result[4], 10)
+    }
+
+    #[tokio::test]
+    async fn test_encode_with_draft() {
+        let mut server = mockito::Server::new_async().await;
+        let _m = server
+            .mock("GET", "/subjects/testresult-value/versions/latest")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(get_json_body(
+                r#"{\"type\":\"object\",\"properties\":{\"up\":{\"type\":\"string\"}},\"unevaluatedProperties\":false}"#,
+                10,
+            ))
+            .create();
+
+        let draft_7 = JsonEncoder::new(SrSettings::new(server.url()));
+        let draft_2019_09 = JsonEncoder::new_with_draft(
+            SrSettings::new(server.url()),
+            JsonSchemaDraft::Draft2019_09,
+        );
+        let strategy = SubjectNameStrategy::TopicNameStrategy(String::from("testresult"), false);
+        let value: Value = serde_json::from_str(r#"{"up":"STRING","down":"string"}"#).unwrap();
+
+        assert!(draft_7.encode(&value, strategy.clone()).await.is_ok());
+        assert!(draft_2019_09.encode(&value, strategy).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_encode_skip_validation_on_compile_error() {
+        let mut server = mockito::Server::new_async().await;
+        let _m = server
+            .mock("GET", "/subjects/testresult-value/versions/latest")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(get_json_body(
+                r#"{\"type\":\"object\",\"properties\":{\"up\":{\"type\":\"string\",\"pattern\":\"(?<=a+)b\"}}}"#,
+                10,
+            ))
+            .create();
+
+        let strict = JsonEncoder::new(SrSettings::new(server.url()));
+        let mut lenient = JsonEncoder::new(SrSettings::new(server.url()));
+        lenient.set_skip_validation_on_compile_error(true);
+        let strategy = SubjectNameStrategy::TopicNameStrategy(String::from("testresult"), false);
+        let value: Value = serde_json::from_str(r#"{"up":"aab"}"#).unwrap();
+
+        let error = strict.encode(&value, strategy.clone()).await.unwrap_err();
+        assert_eq!(error.error, "error compiling schema");
+        let bytes = lenient.encode(&value, strategy.clone()).await.unwrap();
+        assert_eq!(bytes[4], 10);
+        assert!(lenient.encode(&value, strategy).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_decode_validate_with_draft() {
+        let mut server = mockito::Server::new_async().await;
+        let _m = server
+            .mock("GET", "/subjects/testresult-value/versions/latest")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(get_json_body(
+                r#"{\"type\":\"object\",\"properties\":{\"up\":{\"type\":\"string\"}},\"unevaluatedProperties\":false}"#,
+                10,
+            ))
+            .create();
+
+        let draft_7 = JsonDecoder::new(SrSettings::new(server.url()));
+        let draft_2019_09 = JsonDecoder::new_with_draft(
+            SrSettings::new(server.url()),
+            JsonSchemaDraft::Draft2019_09,
+        );
+        let strategy = SubjectNameStrategy::TopicNameStrategy(String::from("testresult"), false);
+        let mut bytes = vec![0, 0, 0, 0, 10];
+        bytes.extend_from_slice(br#"{"up":"STRING","down":"string"}"#);
+
+        assert!(draft_7
+            .decode_validate_against_subject(Some(&bytes), &strategy)
+            .await
+            .is_ok());
+        assert!(draft_2019_09
+            .decode_validate_against_subject(Some(&bytes), &strategy)
+            .await
+            .is_err());
+    }
+
     #[tokio::test]
     async fn decode_invalid_bytes() {
         let sr_settings = SrSettings::new(String::from("http://127.0.0.1:1234"));
@@ -761,4 +1135,127 @@ mod tests {
         let result = decoder.decode(Some(&[1, 0])).await.unwrap_err();
         assert_eq!(String::from("Invalid bytes: [1, 0]"), result.error)
     }
+
+    #[tokio::test]
+    async fn decode_validate_against_subject_ignores_id() {
+        let mut server = mockito::Server::new_async().await;
+        let _i = server
+            .mock("GET", "/schemas/ids/10?deleted=true")
+            .expect(0)
+            .create();
+        let _m = server
+            .mock("GET", "/subjects/testresult-value/versions/latest")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(get_json_body(json_result_schema(), 12))
+            .expect(1)
+            .create();
+        let _n = server
+            .mock("GET", "/subjects/other-value/versions/latest")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(get_json_body(
+                r#"{\"type\":\"object\",\"properties\":{\"up\":{\"type\":\"integer\"}}}"#,
+                13,
+            ))
+            .create();
+
+        let sr_settings = SrSettings::new(server.url());
+        let decoder = JsonDecoder::new(sr_settings);
+        let strategy = SubjectNameStrategy::TopicNameStrategy(String::from("testresult"), false);
+        let other = SubjectNameStrategy::TopicNameStrategy(String::from("other"), false);
+
+        for _ in 0..2 {
+            let message = decoder
+                .decode_validate_against_subject(Some(json_result_java_bytes()), &strategy)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(12, message.schema.id);
+        }
+        assert_eq!(1, decoder.compiled_cache.len());
+        let error = decoder
+            .decode_validate_against_subject(Some(json_result_java_bytes()), &other)
+            .await
+            .unwrap_err();
+        assert!(error
+            .error
+            .contains("was not valid according to the schema"));
+        _i.assert();
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn decode_validate_against_subject_after_clear_subject_cache() {
+        let mut server = mockito::Server::new_async().await;
+        let _m = server
+            .mock("GET", "/subjects/testresult-value/versions/latest")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(get_json_body(json_result_schema(), 12))
+            .expect(2)
+            .create();
+
+        let sr_settings = SrSettings::new(server.url());
+        let decoder = JsonDecoder::new(sr_settings);
+        let strategy = SubjectNameStrategy::TopicNameStrategy(String::from("testresult"), false);
+
+        assert!(decoder
+            .decode_validate_against_subject(Some(json_result_java_bytes()), &strategy)
+            .await
+            .is_ok());
+        decoder.clear_subject_cache();
+        assert!(decoder
+            .decode_validate_against_subject(Some(json_result_java_bytes()), &strategy)
+            .await
+            .is_ok());
+        _m.assert();
+    }
+
+    #[derive(Debug, Default)]
+    struct CountingObserver {
+        hits: AtomicUsize,
+        misses: AtomicUsize,
+    }
+
+    impl Observer for CountingObserver {
+        fn on_cache_hit(&self) {
+            self.hits.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn on_cache_miss(&self) {
+            self.misses.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_decode_notifies_observer() {
+        let mut server = mockito::Server::new_async().await;
+        let _m = server
+            .mock("GET", "/schemas/ids/10?deleted=true")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(get_json_body(json_result_schema(), 10))
+            .expect(1)
+            .create();
+
+        let observer = Arc::new(CountingObserver::default());
+        let sr_settings = SrSettings::new_builder(server.url())
+            .set_observer(observer.clone())
+            .build()
+            .unwrap();
+        let decoder = JsonDecoder::new(sr_settings);
+        decoder
+            .decode(Some(json_result_java_bytes()))
+            .await
+            .unwrap();
+        decoder
+            .decode(Some(json_result_java_bytes()))
+            .await
+            .unwrap();
+
+        assert_eq!(observer.misses.load(Ordering::SeqCst), 1);
+        assert_eq!(observer.hits.load(Ordering::SeqCst), 1);
+        _m.assert();
+    }
 }
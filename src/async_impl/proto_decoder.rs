@@ -10,18 +10,21 @@ use crate::async_impl::schema_registry::{
 };
 use crate::error::SRCError;
 use crate::proto_common_types::add_common_files;
-use crate::proto_resolver::{resolve_name, to_index_and_data, MessageResolver};
+use crate::proto_decoder_common::{
+    add_default_values, decode_message, get_message, map_well_known_types,
+};
+use crate::proto_resolver::{imports_of_all, resolve_name, to_index_and_data, MessageResolver};
 use crate::schema_registry_common::{get_bytes_result, BytesResult, RegisteredSchema, SchemaType};
 use protofish::context::Context;
 use protofish::decode::{MessageValue, Value};
 
-type SharedFutureSchema<'a> = Shared<BoxFuture<'a, Result<Arc<Vec<String>>, SRCError>>>;
+type SharedFutureDecodeContext<'a> = Shared<BoxFuture<'a, Result<Arc<DecodeContext>, SRCError>>>;
 
 #[derive(Debug)]
 pub struct ProtoDecoder<'a> {
     sr_settings: SrSettings,
-    direct_cache: DashMap<u32, Arc<Vec<String>>>,
-    cache: DashMap<u32, SharedFutureSchema<'a>>,
+    direct_cache: DashMap<u32, Arc<DecodeContext>>,
+    cache: DashMap<u32, SharedFutureDecodeContext<'a>>,
 }
 
 impl<'a> ProtoDecoder<'a> {
@@ -60,15 +63,93 @@ impl<'a> ProtoDecoder<'a> {
             BytesResult::Invalid(i) => Ok(Value::Bytes(Bytes::from(i))),
         }
     }
+    /// Decodes bytes without magic byte and id, but still starting with the message index, using the
+    /// schema with the given id. This is useful when the id is known out of band, for example for
+    /// data stored without the framing. Returns none when the bytes are none.
+    pub async fn decode_unframed(
+        &self,
+        id: u32,
+        bytes: Option<&[u8]>,
+    ) -> Result<Option<Value>, SRCError> {
+        match bytes {
+            None => Ok(None),
+            Some([]) => Err(SRCError::non_retryable_without_cause(
+                "Bytes are empty, the message index is missing",
+            )),
+            Some(bytes) => Ok(Some(Value::Message(Box::from(
+                self.deserialize(id, bytes).await?,
+            )))),
+        }
+    }
     /// The actual deserialization trying to get the id from the bytes to retrieve the schema, and
     /// using a reader transforms the bytes to a value.
     async fn deserialize(&self, id: u32, bytes: &[u8]) -> Result<MessageValue, SRCError> {
-        let vec_of_schemas = self.get_vec_of_schemas(id).await?;
-        let context = into_decode_context(vec_of_schemas.to_vec())?;
+        let context = self.get_context(id).await?;
         let (index, data) = to_index_and_data(bytes);
         let full_name = resolve_name(&context.resolver, &index)?;
         let message_info = context.context.get_message(&full_name).unwrap();
-        Ok(message_info.decode(&data, &context.context))
+        Ok(decode_message(message_info, &data, &context.context))
+    }
+    /// Decodes bytes into a value, like decode, but as the message with the full name, like
+    /// `nl.openweb.data.Heartbeat`, instead of the message selected by the index in the bytes. This
+    /// can be used when a producer writes the wrong index. Fails when the schema doesn't contain
+    /// the message.
+    pub async fn decode_as(
+        &self,
+        bytes: Option<&[u8]>,
+        message_full_name: &str,
+    ) -> Result<Value, SRCError> {
+        match get_bytes_result(bytes) {
+            BytesResult::Null => Ok(Value::Bytes(Bytes::new())),
+            BytesResult::Valid(id, bytes) => {
+                let context = self.get_context(id).await?;
+                let (_, data) = to_index_and_data(&bytes);
+                let message_info = get_message(&context.context, message_full_name)?;
+                Ok(Value::Message(Box::from(decode_message(
+                    message_info,
+                    &data,
+                    &context.context,
+                ))))
+            }
+            BytesResult::Invalid(i) => Ok(Value::Bytes(Bytes::from(i))),
+        }
+    }
+    /// Decodes bytes into a value, like decode, but with the default value added for proto3 scalar
+    /// and enum fields that are not in the bytes, see
+    /// [add_default_values](crate::proto_decoder_common::add_default_values).
+    pub async fn decode_with_default_values(
+        &self,
+        bytes: Option<&[u8]>,
+    ) -> Result<Value, SRCError> {
+        match get_bytes_result(bytes) {
+            BytesResult::Null => Ok(Value::Bytes(Bytes::new())),
+            BytesResult::Valid(id, bytes) => {
+                let result = self.deserialize_with_context(id, &bytes).await?;
+                Ok(Value::Message(Box::from(add_default_values(
+                    result.value,
+                    &result.context.context,
+                ))))
+            }
+            BytesResult::Invalid(i) => Ok(Value::Bytes(Bytes::from(i))),
+        }
+    }
+    /// Decodes bytes into a value, like decode, but with the google.protobuf.Timestamp and
+    /// google.protobuf.Duration values replaced by strings, as they would be mapped to json.
+    pub async fn decode_with_well_known_types(
+        &self,
+        bytes: Option<&[u8]>,
+    ) -> Result<Value, SRCError> {
+        match get_bytes_result(bytes) {
+            BytesResult::Null => Ok(Value::Bytes(Bytes::new())),
+            BytesResult::Valid(id, bytes) => {
+                let result = self.deserialize_with_context(id, &bytes).await?;
+                Ok(Value::Message(Box::from(map_well_known_types(
+                    result.value,
+                    &result.context.context,
+                ))))
+            }
+            BytesResult::Invalid(i) => Ok(Value::Bytes(Bytes::from(i))),
+        }
     }
     /// Decodes bytes into a value.
     /// The choice to use Option<&[u8]> as type us made so it plays nice with the BorrowedMessage
@@ -99,12 +180,11 @@ impl<'a> ProtoDecoder<'a> {
         id: u32,
         bytes: &[u8],
     ) -> Result<DecodeResultWithContext, SRCError> {
-        let vec_of_schemas = self.get_vec_of_schemas(id).await?;
-        let context = into_decode_context(vec_of_schemas.to_vec())?;
+        let context = self.get_context(id).await?;
         let (index, data_bytes) = to_index_and_data(bytes);
         let full_name = resolve_name(&context.resolver, &index)?;
         let message_info = context.context.get_message(&full_name).unwrap();
-        let value = message_info.decode(&data_bytes, &context.context);
+        let value = decode_message(message_info, &data_bytes, &context.context);
         Ok(DecodeResultWithContext {
             value,
             context,
@@ -112,32 +192,43 @@ impl<'a> ProtoDecoder<'a> {
             data_bytes,
         })
     }
-    /// Gets the vector of schema's directly of via a shared future. The direct cache main function
+    /// Gets the context of the schema with the id, which contains the parsed messages and enums,
+    /// including those of the references. This can be used to inspect the schema, for example
+    /// to list the fields of a message.
+    pub async fn get_decode_context(&self, id: u32) -> Result<Arc<DecodeContext>, SRCError> {
+        self.get_context(id).await
+    }
+    /// Gets the decode context directly of via a shared future. The direct cache main function
     /// is for performance.
-    async fn get_vec_of_schemas(&self, id: u32) -> Result<Arc<Vec<String>>, SRCError> {
+    async fn get_context(&self, id: u32) -> Result<Arc<DecodeContext>, SRCError> {
         match self.direct_cache.get(&id) {
             None => {
-                let result = self.get_vec_of_schemas_by_shared_future(id).await;
+                self.sr_settings.observe_cache_miss();
+                let result = self.get_context_by_shared_future(id).await;
                 if result.is_ok() && !self.direct_cache.contains_key(&id) {
                     self.direct_cache.insert(id, result.clone().unwrap());
                     self.cache.remove(&id);
                 };
                 result
             }
-            Some(result) => Ok(result.value().clone()),
+            Some(result) => {
+                self.sr_settings.observe_cache_hit();
+                Ok(result.value().clone())
+            }
         }
     }
-    /// Gets the vector of schema's by a shared future, to prevent multiple of the same calls to
+    /// Gets the decode context by a shared future, to prevent multiple of the same calls to
     /// schema registry, either from the cache, or from the schema registry and then putting
-    /// it into the cache.
-    fn get_vec_of_schemas_by_shared_future(&self, id: u32) -> SharedFutureSchema<'a> {
+    /// it into the cache. The parsed context is cached, so the schema's only need to be parsed
+    /// once.
+    fn get_context_by_shared_future(&self, id: u32) -> SharedFutureDecodeContext<'a> {
         match self.cache.entry(id) {
             Entry::Occupied(e) => e.get().clone(),
             Entry::Vacant(e) => {
                 let sr_settings = self.sr_settings.clone();
                 let v = async move {
                     match get_schema_by_id_and_type(id, &sr_settings, SchemaType::Protobuf).await {
-                        Ok(v) => to_vec_of_schemas(&sr_settings, v).await,
+                        Ok(v) => to_decode_context(&sr_settings, v).await,
                         Err(e) => Err(e.into_cache()),
                     }
                 }
@@ -152,7 +243,7 @@ impl<'a> ProtoDecoder<'a> {
 #[derive(Debug)]
 pub struct DecodeResultWithContext {
     pub value: MessageValue,
-    pub context: DecodeContext,
+    pub context: Arc<DecodeContext>,
     pub full_name: Arc<String>,
     pub data_bytes: Vec<u8>,
 }
@@ -167,7 +258,9 @@ fn add_files<'a>(
             let child_schema = get_referenced_schema(sr_settings, &r).await?;
             add_files(sr_settings, child_schema, files).await?;
         }
-        files.push(registered_schema.schema);
+        if !files.contains(&registered_schema.schema) {
+            files.push(registered_schema.schema);
+        }
         Ok(())
     }
     .boxed()
@@ -182,7 +275,7 @@ pub struct DecodeContext {
 fn into_decode_context(vec_of_schemas: Vec<String>) -> Result<DecodeContext, SRCError> {
     let resolver = MessageResolver::new(vec_of_schemas.last().unwrap());
     let mut files: Vec<String> = Vec::new();
-    add_common_files(resolver.imports(), &mut files);
+    add_common_files(&imports_of_all(&vec_of_schemas), &mut files);
     for s in vec_of_schemas {
         files.push(s);
     }
@@ -195,13 +288,13 @@ fn into_decode_context(vec_of_schemas: Vec<String>) -> Result<DecodeContext, SRC
     }
 }
 
-async fn to_vec_of_schemas(
+async fn to_decode_context(
     sr_settings: &SrSettings,
     registered_schema: RegisteredSchema,
-) -> Result<Arc<Vec<String>>, SRCError> {
+) -> Result<Arc<DecodeContext>, SRCError> {
     let mut vec_of_schemas = Vec::new();
     add_files(sr_settings, registered_schema, &mut vec_of_schemas).await?;
-    Ok(Arc::new(vec_of_schemas))
+    Ok(Arc::new(into_decode_context(vec_of_schemas)?))
 }
 
 #[cfg(test)]
@@ -275,6 +368,167 @@ mod tests {
         assert_eq!(Value::UInt64(101u64), message.fields[0].value)
     }
 
+    #[tokio::test]
+    async fn test_decoder_default_values() {
+        let mut server = mockito::Server::new_async().await;
+        let _m = server
+            .mock("GET", "/schemas/ids/4?deleted=true")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(get_proto_body(
+                r#"syntax = \"proto3\"; package nl.openweb.data; message Heartbeat {uint64 beat = 1; optional string source = 2;}"#,
+                4,
+            ))
+            .create();
+
+        let sr_settings = SrSettings::new(server.url());
+        let decoder = ProtoDecoder::new(sr_settings);
+        let heartbeat = decoder
+            .decode_with_default_values(Some(&[0, 0, 0, 0, 4, 0]))
+            .await
+            .unwrap();
+
+        let message = match heartbeat {
+            Value::Message(x) => *x,
+            v => panic!("Other value: {:?} than expected Message", v),
+        };
+        assert_eq!(1, message.fields.len());
+        assert_eq!(Value::UInt64(0), message.fields[0].value)
+    }
+
+    #[tokio::test]
+    async fn test_get_decode_context() {
+        let mut server = mockito::Server::new_async().await;
+        let _m = server
+            .mock("GET", "/schemas/ids/7?deleted=true")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(get_proto_body(get_proto_hb_schema(), 1))
+            .create();
+
+        let sr_settings = SrSettings::new(server.url());
+        let decoder = ProtoDecoder::new(sr_settings);
+        let context = decoder.get_decode_context(7).await.unwrap();
+
+        let message_info = context
+            .context
+            .get_message("nl.openweb.data.Heartbeat")
+            .unwrap();
+        let names: Vec<&str> = message_info
+            .iter_fields()
+            .map(|f| f.name.as_str())
+            .collect();
+        assert_eq!(vec!["beat"], names);
+
+        let cached = decoder.get_decode_context(7).await.unwrap();
+        assert!(std::sync::Arc::ptr_eq(&context, &cached))
+    }
+
+    #[tokio::test]
+    async fn test_decode_as() {
+        let mut server = mockito::Server::new_async().await;
+        let _m = server
+            .mock("GET", "/schemas/ids/5?deleted=true")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(get_proto_body(
+                r#"syntax = \"proto3\"; package nl.openweb.data; message Other {string name = 1;} message Heartbeat {uint64 beat = 1;}"#,
+                5,
+            ))
+            .create();
+
+        let sr_settings = SrSettings::new(server.url());
+        let decoder = ProtoDecoder::new(sr_settings);
+        let heartbeat = decoder
+            .decode_as(
+                Some(&[0, 0, 0, 0, 5, 0, 8, 101]),
+                "nl.openweb.data.Heartbeat",
+            )
+            .await
+            .unwrap();
+
+        let message = match heartbeat {
+            Value::Message(x) => *x,
+            v => panic!("Other value: {:?} than expected Message", v),
+        };
+        assert_eq!(Value::UInt64(101u64), message.fields[0].value);
+
+        let error = decoder
+            .decode_as(Some(&[0, 0, 0, 0, 5, 0, 8, 101]), "nl.openweb.data.Missing")
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.error,
+            "Could not find message nl.openweb.data.Missing in the schema"
+        )
+    }
+
+    #[tokio::test]
+    async fn test_decode_unframed() {
+        let mut server = mockito::Server::new_async().await;
+        let _m = server
+            .mock("GET", "/schemas/ids/7?deleted=true")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(get_proto_body(get_proto_hb_schema(), 1))
+            .create();
+
+        let sr_settings = SrSettings::new(server.url());
+        let decoder = ProtoDecoder::new(sr_settings);
+        let heartbeat = decoder
+            .decode_unframed(7, Some(&get_proto_hb_101()[5..]))
+            .await
+            .unwrap();
+
+        let message = match heartbeat {
+            Some(Value::Message(x)) => *x,
+            v => panic!("Other value: {:?} than expected Message", v),
+        };
+        assert_eq!(Value::UInt64(101u64), message.fields[0].value);
+        assert_eq!(None, decoder.decode_unframed(7, None).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_decode_unframed_empty() {
+        let sr_settings = SrSettings::new(String::from("http://127.0.0.1:1234"));
+        let decoder = ProtoDecoder::new(sr_settings);
+        let error = decoder.decode_unframed(7, Some(&[])).await.unwrap_err();
+        assert_eq!(error.error, "Bytes are empty, the message index is missing");
+        assert!(!error.retriable);
+    }
+
+    #[tokio::test]
+    async fn test_decoder_well_known_types() {
+        let mut server = mockito::Server::new_async().await;
+        let _m = server
+            .mock("GET", "/schemas/ids/3?deleted=true")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(get_proto_body(
+                r#"syntax = \"proto3\"; package nl.openweb.data; import \"google/protobuf/timestamp.proto\"; message Event {google.protobuf.Timestamp at = 1;}"#,
+                3,
+            ))
+            .create();
+
+        let sr_settings = SrSettings::new(server.url());
+        let decoder = ProtoDecoder::new(sr_settings);
+        let event = decoder
+            .decode_with_well_known_types(Some(&[
+                0, 0, 0, 0, 3, 0, 10, 12, 8, 128, 204, 185, 255, 5, 16, 128, 202, 181, 238, 1,
+            ]))
+            .await
+            .unwrap();
+
+        let message = match event {
+            Value::Message(x) => *x,
+            v => panic!("Other value: {:?} than expected Message", v),
+        };
+        assert_eq!(
+            Value::String(String::from("2021-01-01T00:00:00.500Z")),
+            message.fields[0].value
+        )
+    }
+
     #[tokio::test]
     async fn test_decoder_cache() {
         let mut server = mockito::Server::new();
@@ -305,6 +559,60 @@ mod tests {
         assert_eq!(Value::UInt64(101u64), message.fields[0].value);
     }
 
+    #[tokio::test]
+    async fn test_decoder_shared_reference_with_well_known_types() {
+        let mut server = mockito::Server::new_async().await;
+        let _m = server
+            .mock("GET", "/schemas/ids/9?deleted=true")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(get_proto_body_with_reference(
+                r#"syntax = \"proto3\"; package nl.openweb.data; import \"a.proto\"; import \"event.proto\"; message Holder {A a = 1; Event event = 2;}"#,
+                9,
+                r#"{"name": "a.proto", "subject": "a", "version": 1}, {"name": "event.proto", "subject": "event", "version": 1}"#,
+            ))
+            .create();
+        let _m = server
+            .mock("GET", "/subjects/a/versions/1")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(get_proto_body_with_reference(
+                r#"syntax = \"proto3\"; package nl.openweb.data; import \"event.proto\"; message A {Event event = 1;}"#,
+                10,
+                r#"{"name": "event.proto", "subject": "event", "version": 1}"#,
+            ))
+            .create();
+        let _m = server
+            .mock("GET", "/subjects/event/versions/1")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(get_proto_body(
+                r#"syntax = \"proto3\"; package nl.openweb.data; import \"google/protobuf/timestamp.proto\"; message Event {google.protobuf.Timestamp at = 1;}"#,
+                11,
+            ))
+            .create();
+
+        let sr_settings = SrSettings::new(server.url());
+        let decoder = ProtoDecoder::new(sr_settings);
+        let holder = decoder
+            .decode_with_well_known_types(Some(&[0, 0, 0, 0, 9, 0, 18, 4, 10, 2, 8, 5]))
+            .await
+            .unwrap();
+
+        let message = match holder {
+            Value::Message(x) => *x,
+            v => panic!("Other value: {:?} than expected Message", v),
+        };
+        let event = match &message.fields[0].value {
+            Value::Message(x) => x,
+            v => panic!("Other value: {:?} than expected Message", v),
+        };
+        assert_eq!(
+            Value::String(String::from("1970-01-01T00:00:05Z")),
+            event.fields[0].value
+        )
+    }
+
     #[tokio::test]
     async fn test_decoder_complex() {
         let mut server = mockito::Server::new();
@@ -345,7 +653,7 @@ mod tests {
         let sr_settings = SrSettings::new(String::from("http://127.0.0.1:1234"));
         let decoder = ProtoDecoder::new(sr_settings);
         assert!(
-            format!("{:?}", decoder).starts_with("ProtoDecoder { sr_settings: SrSettings { urls: [\"http://127.0.0.1:1234\"], client: Client {")
+            format!("{:?}", decoder).starts_with("ProtoDecoder { sr_settings: SrSettings { urls: [\"http://127.0.0.1:1234\"], authorization: None }")
         )
     }
 }
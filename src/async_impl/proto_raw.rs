@@ -82,6 +82,7 @@ impl<'a> ProtoRawEncoder<'a> {
     ) -> Result<Arc<EncodeContext>, SRCError> {
         match self.direct_cache.get(&key) {
             None => {
+                self.sr_settings.observe_cache_miss();
                 let result = self
                     .get_encoding_context_by_shared_future(key.clone(), subject_name_strategy)
                     .await;
@@ -92,7 +93,10 @@ impl<'a> ProtoRawEncoder<'a> {
                 };
                 result
             }
-            Some(result) => Ok(result.value().clone()),
+            Some(result) => {
+                self.sr_settings.observe_cache_hit();
+                Ok(result.value().clone())
+            }
         }
     }
 
@@ -180,6 +184,7 @@ impl<'a> ProtoRawDecoder<'a> {
     async fn get_context(&self, id: u32) -> Result<Arc<DecodeContext>, SRCError> {
         match self.direct_cache.get(&id) {
             None => {
+                self.sr_settings.observe_cache_miss();
                 let result = self.get_context_by_shared_future(id).await;
                 if result.is_ok() && !self.direct_cache.contains_key(&id) {
                     self.direct_cache.insert(id, result.clone().unwrap());
@@ -187,7 +192,10 @@ impl<'a> ProtoRawDecoder<'a> {
                 };
                 result
             }
-            Some(result) => Ok(result.value().clone()),
+            Some(result) => {
+                self.sr_settings.observe_cache_hit();
+                Ok(result.value().clone())
+            }
         }
     }
     /// Gets the Context object, either from the cache, or from the schema registry and then putting
@@ -409,7 +417,7 @@ mod tests {
         let sr_settings = SrSettings::new(String::from("http://127.0.0.1:1234"));
         let decoder = ProtoRawEncoder::new(sr_settings);
         assert!(
-            format!("{:?}", decoder).starts_with("ProtoRawEncoder { sr_settings: SrSettings { urls: [\"http://127.0.0.1:1234\"], client: Client")
+            format!("{:?}", decoder).starts_with("ProtoRawEncoder { sr_settings: SrSettings { urls: [\"http://127.0.0.1:1234\"], authorization: None }")
         )
     }
 
@@ -503,7 +511,7 @@ mod tests {
         let sr_settings = SrSettings::new(String::from("http://127.0.0.1:1234"));
         let decoder = ProtoRawDecoder::new(sr_settings);
         assert!(
-            format!("{:?}", decoder).starts_with("ProtoRawDecoder { sr_settings: SrSettings { urls: [\"http://127.0.0.1:1234\"], client: Client")
+            format!("{:?}", decoder).starts_with("ProtoRawDecoder { sr_settings: SrSettings { urls: [\"http://127.0.0.1:1234\"], authorization: None }")
         )
     }
 }
@@ -1,13 +1,22 @@
+use apache_avro::rabin::Rabin;
 use apache_avro::schema::{Name, Schema};
+use apache_avro::schema_compatibility::SchemaCompatibility;
 use apache_avro::types::{Record, Value};
-use apache_avro::{to_avro_datum, to_value};
+use apache_avro::{from_avro_datum, from_value, to_avro_datum, to_value};
 use dashmap::DashMap;
+use serde::de::DeserializeOwned;
 use serde::ser::Serialize;
 use serde_json::{value, Map};
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
 use std::sync::Arc;
+use std::time::Duration;
 
 use crate::error::SRCError;
-use crate::schema_registry_common::{get_payload, SchemaType, SuppliedSchema};
+use crate::schema_registry_common::{
+    put_header, IdEncoding, RegisteredReference, SchemaType, SubjectNameStrategy, SuppliedSchema,
+};
 
 /// Because we need both the resulting schema, as have a way of posting the schema as json, we use
 /// this struct so we keep them both together.
@@ -31,81 +40,302 @@ pub struct DecodeResultWithSchema {
     pub schema: Arc<AvroSchema>,
 }
 
-fn might_replace(
-    val: value::Value,
-    child: &value::Value,
-    replace_values: &DashMap<String, String>,
-) -> value::Value {
-    match val {
-        value::Value::Object(v) => replace_in_map(v, child, replace_values),
-        value::Value::Array(v) => replace_in_array(&v, child, replace_values),
-        value::Value::String(s) if replace_values.contains_key(&*s) => child.clone(),
-        p => p,
+/// Encoded bytes together with the subject and the id of the schema that were used to encode them.
+#[derive(Debug, PartialEq)]
+pub struct EncodeResult {
+    pub subject: String,
+    pub id: u32,
+    pub bytes: Vec<u8>,
+}
+
+/// Decoded value together with the raw bytes it was decoded from, without the magic byte and id.
+#[derive(Debug, PartialEq)]
+pub struct DecodeResultWithRaw {
+    pub name: Option<Name>,
+    pub value: Value,
+    pub raw: Vec<u8>,
+}
+
+/// Timing information of a single decode, can be used to profile consumers. The fetch duration
+/// includes getting the schema from the cache, or from the schema registry when it's not cached
+/// yet.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct DecodeTiming {
+    pub fetch: Duration,
+    pub decode: Duration,
+    pub cache_hit: bool,
+}
+
+/// Transforms the bytes, without the magic byte and id, to a value using the schema. When a reader
+/// schema is supplied the value is resolved against it. When strict length is set it's an error
+/// when not all the bytes are used.
+pub(crate) fn bytes_to_value(
+    schema: &Schema,
+    reader_schema: Option<&Schema>,
+    bytes: &[u8],
+    strict_length: bool,
+) -> Result<Value, SRCError> {
+    let mut reader = Cursor::new(bytes);
+    let value = match from_avro_datum(schema, &mut reader, reader_schema) {
+        Ok(v) => v,
+        Err(e) => {
+            return Err(SRCError::non_retryable_with_cause(
+                e,
+                "Could not transform bytes using schema",
+            ))
+        }
+    };
+    let remaining = bytes.len() - reader.position() as usize;
+    if strict_length && remaining > 0 {
+        return Err(SRCError::non_retryable_without_cause(&format!(
+            "{} bytes remained after transforming the bytes using schema",
+            remaining
+        )));
+    }
+    Ok(value)
+}
+
+/// Fails when a maximum payload length is set, and the bytes, without the magic byte and id, are
+/// longer. This is checked before the schema is fetched, so corrupted bytes can't lead to
+/// decoding huge values.
+pub(crate) fn check_payload_len(
+    bytes: &[u8],
+    max_payload_len: Option<usize>,
+) -> Result<(), SRCError> {
+    match max_payload_len {
+        Some(max) if bytes.len() > max => Err(SRCError::non_retryable_without_cause(&format!(
+            "payload of {} bytes is larger than the maximum of {} bytes",
+            bytes.len(),
+            max
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Reads the schemas from the `.avsc` files in the directory, where the file name is the id of the
+/// schema, like `5.avsc`. Named types defined in one of the files can be used by name in the other
+/// files, they are inlined the same way as references from the schema registry. Other files are
+/// ignored.
+pub fn load_schemas_from_dir(dir: &Path) -> Result<Vec<AvroSchema>, SRCError> {
+    let schemas =
+        load_keyed_schemas_from_dir(dir, |stem| stem.parse::<u32>().ok().map(|id| ((), id)))?;
+    Ok(schemas.into_iter().map(|(_, schema)| schema).collect())
+}
+
+/// Reads the schemas from the `.avsc` files in the directory, where the file name is the subject
+/// and the id of the schema, like `heartbeat-value.3.avsc`. As the subject may contain dots, only
+/// the part after the last dot is used as the id. Named types can be used by name in the other
+/// files, the same as with [load_schemas_from_dir]. Other files are ignored.
+pub fn load_subject_schemas_from_dir(dir: &Path) -> Result<Vec<(String, AvroSchema)>, SRCError> {
+    load_keyed_schemas_from_dir(dir, |stem| {
+        let (subject, id) = stem.rsplit_once('.')?;
+        Some((String::from(subject), id.parse::<u32>().ok()?))
+    })
+}
+
+fn load_keyed_schemas_from_dir<K>(
+    dir: &Path,
+    parse_stem: impl Fn(&str) -> Option<(K, u32)>,
+) -> Result<Vec<(K, AvroSchema)>, SRCError> {
+    let read_error = |e: std::io::Error| {
+        SRCError::non_retryable_with_cause(e, &format!("could not read schemas from {:?}", dir))
+    };
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir).map_err(read_error)? {
+        let path = entry.map_err(read_error)?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("avsc") {
+            continue;
+        }
+        let (key, id) = match path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .and_then(&parse_stem)
+        {
+            Some(v) => v,
+            None => continue,
+        };
+        let raw = fs::read_to_string(&path).map_err(read_error)?;
+        let json: value::Value = serde_json::from_str(&raw).map_err(|e| {
+            SRCError::non_retryable_with_cause(e, &format!("could not parse {:?} as json", path))
+        })?;
+        files.push((key, id, raw, json));
     }
+    files.sort_by_key(|(_, id, _, _)| *id);
+    let all: Vec<value::Value> = files.iter().map(|(_, _, _, json)| json.clone()).collect();
+    files
+        .into_iter()
+        .enumerate()
+        .map(|(i, (key, id, raw, json))| {
+            let references: Vec<value::Value> = all
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| *j != i)
+                .map(|(_, r)| r.clone())
+                .collect();
+            let parsed = Schema::parse(&inline_references(json, &references)).map_err(|e| {
+                SRCError::non_retryable_with_cause(
+                    e,
+                    &format!("could not parse schema with id {} as avro", id),
+                )
+            })?;
+            Ok((key, AvroSchema { id, raw, parsed }))
+        })
+        .collect()
 }
 
-fn replace_in_array(
-    parent_array: &[value::Value],
-    child: &value::Value,
-    replace_values: &DashMap<String, String>,
+/// Fails when the reference is already being resolved further up the chain, which means the
+/// references contain a cycle, and resolving them would never end.
+pub(crate) fn check_reference_cycle(
+    resolving: &[(String, u32)],
+    reference: &RegisteredReference,
+) -> Result<(), SRCError> {
+    if resolving
+        .iter()
+        .any(|(subject, version)| *subject == reference.subject && *version == reference.version)
+    {
+        return Err(SRCError::non_retryable_without_cause(&format!(
+            "reference {:?} refers back to itself",
+            reference
+        )));
+    }
+    Ok(())
+}
+
+/// Inlines the definitions of the referenced schemas into the schema, so it can be parsed on its
+/// own. The schema is walked in the order it's parsed, and each referenced named type is inlined
+/// where it's first used, later uses keep referring to it by name. This way a type that is
+/// referenced from multiple places, or multiple times, is only defined once. Like with the Java
+/// client, a name without namespace is first looked up in the enclosing namespace, and then as is.
+pub(crate) fn inline_references(schema: value::Value, references: &[value::Value]) -> value::Value {
+    let mut defined = Vec::new();
+    inline_in_type(schema, None, references, &mut defined)
+}
+
+fn inline_in_type(
+    value: value::Value,
+    namespace: Option<&str>,
+    references: &[value::Value],
+    defined: &mut Vec<String>,
 ) -> value::Value {
-    value::Value::Array(
-        parent_array
-            .iter()
-            .map(|v| might_replace(v.clone(), child, replace_values))
-            .collect(),
-    )
-}
-
-fn replace_in_map(
-    parent_map: Map<String, value::Value>,
-    child: &value::Value,
-    replace_values: &DashMap<String, String>,
+    match value {
+        value::Value::String(name) => match find_reference(&name, namespace, references, defined) {
+            Some(reference) => inline_in_type(reference.clone(), None, references, defined),
+            None => value::Value::String(name),
+        },
+        value::Value::Array(union) => value::Value::Array(
+            union
+                .into_iter()
+                .map(|v| inline_in_type(v, namespace, references, defined))
+                .collect(),
+        ),
+        value::Value::Object(map) => inline_in_map(map, namespace, references, defined),
+        v => v,
+    }
+}
+
+fn inline_in_map(
+    mut map: Map<String, value::Value>,
+    namespace: Option<&str>,
+    references: &[value::Value],
+    defined: &mut Vec<String>,
 ) -> value::Value {
-    value::Value::Object(
-        parent_map
-            .iter()
-            .map(|e| {
-                (
-                    e.0.clone(),
-                    might_replace(e.1.clone(), child, replace_values),
-                )
+    let namespace = match full_name(&map, namespace) {
+        Some((full_name, own_namespace)) => {
+            defined.push(full_name);
+            own_namespace
+        }
+        None => namespace.map(String::from),
+    };
+    let namespace = namespace.as_deref();
+    for key in ["type", "items", "values"] {
+        if let Some(v) = map.remove(key) {
+            map.insert(
+                String::from(key),
+                inline_in_type(v, namespace, references, defined),
+            );
+        }
+    }
+    if let Some(value::Value::Array(fields)) = map.remove("fields") {
+        let fields = fields
+            .into_iter()
+            .map(|field| match field {
+                value::Value::Object(mut f) => {
+                    if let Some(v) = f.remove("type") {
+                        f.insert(
+                            String::from("type"),
+                            inline_in_type(v, namespace, references, defined),
+                        );
+                    }
+                    value::Value::Object(f)
+                }
+                f => f,
             })
-            .collect(),
-    )
+            .collect();
+        map.insert(String::from("fields"), value::Value::Array(fields));
+    }
+    value::Value::Object(map)
 }
 
-pub(crate) fn replace_reference(parent: value::Value, child: value::Value) -> value::Value {
-    let (name, namespace) = match &child {
-        value::Value::Object(v) => (v["name"].as_str(), v["namespace"].as_str()),
-        _ => return parent,
-    };
-    let replace_values: DashMap<String, String> = DashMap::new();
-    match name {
-        Some(v) => match namespace {
-            Some(u) => {
-                let key = format!(".{}.{}", u, v);
-                replace_values.insert(key.clone(), key);
-                if parent["namespace"].as_str() == namespace {
-                    replace_values.insert(String::from(v), String::from(v));
+/// The full name and namespace of a named type definition, none for other types.
+fn full_name(
+    map: &Map<String, value::Value>,
+    namespace: Option<&str>,
+) -> Option<(String, Option<String>)> {
+    match map.get("type").and_then(|t| t.as_str()) {
+        Some("record") | Some("error") | Some("enum") | Some("fixed") => (),
+        _ => return None,
+    }
+    let name = map.get("name")?.as_str()?;
+    match name.rsplit_once('.') {
+        Some((ns, _)) => Some((String::from(name), Some(String::from(ns)))),
+        None => {
+            let ns = match map.get("namespace").and_then(|n| n.as_str()) {
+                Some(n) => Some(n),
+                None => namespace,
+            };
+            match ns {
+                Some(n) if !n.is_empty() => {
+                    Some((format!("{}.{}", n, name), Some(String::from(n))))
                 }
+                _ => Some((String::from(name), None)),
             }
-            None => {
-                replace_values.insert(String::from(v), String::from(v));
-            }
-        },
-        None => return parent,
-    };
-    match parent {
-        value::Value::Object(v) => replace_in_map(v, &child, &replace_values),
-        value::Value::Array(v) => replace_in_array(&v, &child, &replace_values),
-        p => p,
+        }
     }
 }
 
-fn to_bytes(avro_schema: &AvroSchema, record: Value) -> Result<Vec<u8>, SRCError> {
+/// Finds the referenced schema for a name used as a type, when it's not defined yet.
+fn find_reference<'r>(
+    name: &str,
+    namespace: Option<&str>,
+    references: &'r [value::Value],
+    defined: &[String],
+) -> Option<&'r value::Value> {
+    let name = name.trim_start_matches('.');
+    let mut candidates = vec![String::from(name)];
+    if let Some(n) = namespace {
+        if !name.contains('.') {
+            candidates.insert(0, format!("{}.{}", n, name));
+        }
+    }
+    for candidate in candidates {
+        if defined.contains(&candidate) {
+            return None;
+        }
+        let found = references.iter().find(|r| match r {
+            value::Value::Object(m) => {
+                full_name(m, None).map(|(full_name, _)| full_name) == Some(candidate.clone())
+            }
+            _ => false,
+        });
+        if found.is_some() {
+            return found;
+        }
+    }
+    None
+}
+fn to_datum(avro_schema: &AvroSchema, record: Value) -> Result<Vec<u8>, SRCError> {
     match to_avro_datum(&avro_schema.parsed, record) {
-        Ok(v) => Ok(get_payload(avro_schema.id, v)),
+        Ok(v) => Ok(v),
         Err(e) => Err(SRCError::non_retryable_with_cause(
             e,
             "Could not get Avro bytes",
@@ -113,11 +343,25 @@ fn to_bytes(avro_schema: &AvroSchema, record: Value) -> Result<Vec<u8>, SRCError
     }
 }
 
+fn to_bytes(
+    avro_schema: &AvroSchema,
+    record: Value,
+    id_encoding: IdEncoding,
+) -> Result<Vec<u8>, SRCError> {
+    let datum = to_datum(avro_schema, record)?;
+    let mut payload = Vec::with_capacity(1 + id_encoding.id_len() + datum.len());
+    put_header(avro_schema.id, id_encoding, &mut payload);
+    payload.extend_from_slice(&datum);
+    Ok(payload)
+}
+
 /// Using the schema with a vector of values the values will be correctly deserialized according to
-/// the avro specification.
+/// the avro specification. The record is resolved against the schema first, like items are, so
+/// values for logical types, like bytes for a decimal or a string for an uuid, are converted.
 pub(crate) fn values_to_bytes(
     avro_schema: &AvroSchema,
     values: Vec<(&str, Value)>,
+    id_encoding: IdEncoding,
 ) -> Result<Vec<u8>, SRCError> {
     let mut record = match Record::new(&avro_schema.parsed) {
         Some(v) => v,
@@ -132,7 +376,10 @@ pub(crate) fn values_to_bytes(
     for value in values {
         record.put(value.0, value.1)
     }
-    to_bytes(avro_schema, Value::from(record))
+    let record = Value::from(record)
+        .resolve(&avro_schema.parsed)
+        .map_err(|e| SRCError::non_retryable_with_cause(e, "Could not get Avro bytes"))?;
+    to_bytes(avro_schema, record, id_encoding)
 }
 
 /// Using the schema with an item implementing serialize the item will be correctly deserialized
@@ -140,19 +387,199 @@ pub(crate) fn values_to_bytes(
 pub(crate) fn item_to_bytes(
     avro_schema: &AvroSchema,
     item: impl Serialize,
+    id_encoding: IdEncoding,
+) -> Result<Vec<u8>, SRCError> {
+    to_bytes(avro_schema, item_to_value(avro_schema, item)?, id_encoding)
+}
+
+/// Like item_to_bytes, but appending the bytes to the buffer, so the buffer can be reused.
+pub(crate) fn item_to_bytes_into(
+    avro_schema: &AvroSchema,
+    item: impl Serialize,
+    buffer: &mut Vec<u8>,
+    id_encoding: IdEncoding,
+) -> Result<(), SRCError> {
+    let datum = item_to_datum(avro_schema, item)?;
+    buffer.reserve(1 + id_encoding.id_len() + datum.len());
+    put_header(avro_schema.id, id_encoding, buffer);
+    buffer.extend_from_slice(&datum);
+    Ok(())
+}
+
+/// Gets just the avro encoded bytes of the item, without the magic byte and id.
+pub(crate) fn item_to_datum(
+    avro_schema: &AvroSchema,
+    item: impl Serialize,
 ) -> Result<Vec<u8>, SRCError> {
+    to_datum(avro_schema, item_to_value(avro_schema, item)?)
+}
+
+fn item_to_value(avro_schema: &AvroSchema, item: impl Serialize) -> Result<Value, SRCError> {
     match to_value(item)
         .map_err(|e| {
             SRCError::non_retryable_with_cause(e, "Could not transform to apache_avro value")
         })
         .map(|r| r.resolve(&avro_schema.parsed))
     {
-        Ok(Ok(v)) => to_bytes(avro_schema, v),
+        Ok(Ok(v)) => Ok(v),
         Ok(Err(e)) => Err(SRCError::non_retryable_with_cause(e, "Failed to resolve")),
         Err(e) => Err(e),
     }
 }
 
+pub(crate) fn value_to_item<T: DeserializeOwned>(value: &Value) -> Result<T, SRCError> {
+    from_value::<T>(value).map_err(|e| {
+        SRCError::non_retryable_with_cause(e, "Could not transform apache_avro value to item")
+    })
+}
+
+/// Marker of the avro single object encoding, it's followed by the 8 byte Rabin fingerprint of the
+/// schema, and then the avro encoded value.
+const SINGLE_OBJECT_MARKER: [u8; 2] = [0xC3, 0x01];
+
+/// Gets the CRC-64-AVRO (Rabin) fingerprint of the schema, as used in single object encoding.
+pub fn get_fingerprint(schema: &Schema) -> [u8; 8] {
+    let mut fingerprint = [0u8; 8];
+    fingerprint.copy_from_slice(&schema.fingerprint::<Rabin>().bytes[..8]);
+    fingerprint
+}
+
+/// Gets the CRC-64-AVRO (Rabin) fingerprint of the schema as a number, for example to deduplicate
+/// schemas. Other fingerprints, like SHA-256, can be computed with `Schema::fingerprint`.
+pub fn get_rabin_fingerprint(schema: &Schema) -> u64 {
+    u64::from_le_bytes(get_fingerprint(schema))
+}
+
+/// Gets the Parsing Canonical Form of the schema, which is the same for schemas that only differ in
+/// formatting, documentation, or other attributes not needed for parsing.
+pub fn get_canonical_form(schema: &Schema) -> String {
+    schema.canonical_form()
+}
+
+/// Prefixes the avro datum with the single object encoding header.
+pub(crate) fn to_single_object(schema: &Schema, datum: Vec<u8>) -> Vec<u8> {
+    let mut result = Vec::with_capacity(datum.len() + 10);
+    result.extend_from_slice(&SINGLE_OBJECT_MARKER);
+    result.extend_from_slice(&get_fingerprint(schema));
+    result.extend_from_slice(&datum);
+    result
+}
+
+/// Decoder for avro single object encoded bytes, instead of the schema registry framing. The schema
+/// registry can't find schemas by fingerprint, so the schemas that might be used need to be added
+/// before decoding.
+#[derive(Debug, Default)]
+pub struct SingleObjectDecoder {
+    schemas: DashMap<[u8; 8], Arc<Schema>>,
+}
+
+impl SingleObjectDecoder {
+    pub fn new() -> SingleObjectDecoder {
+        SingleObjectDecoder::default()
+    }
+    /// Adds a schema, so values written with it can be decoded.
+    pub fn add_schema(&self, schema: Schema) {
+        self.schemas
+            .insert(get_fingerprint(&schema), Arc::new(schema));
+    }
+    /// Decodes single object encoded bytes into a value, using the schema matching the
+    /// fingerprint.
+    pub fn decode(&self, bytes: Option<&[u8]>) -> Result<DecodeResult, SRCError> {
+        let bytes = match bytes {
+            None => {
+                return Ok(DecodeResult {
+                    name: None,
+                    value: Value::Null,
+                })
+            }
+            Some(p) if p.len() >= 10 && p[..2] == SINGLE_OBJECT_MARKER => p,
+            Some(p) => {
+                return Err(SRCError::non_retryable_without_cause(&format!(
+                    "Invalid bytes {:?}",
+                    p
+                )))
+            }
+        };
+        let mut fingerprint = [0u8; 8];
+        fingerprint.copy_from_slice(&bytes[2..10]);
+        let schema = match self.schemas.get(&fingerprint) {
+            Some(v) => v.value().clone(),
+            None => {
+                return Err(SRCError::non_retryable_without_cause(&format!(
+                    "No schema added for fingerprint {:?}",
+                    fingerprint
+                )))
+            }
+        };
+        match from_avro_datum(&schema, &mut Cursor::new(&bytes[10..]), None) {
+            Ok(value) => Ok(DecodeResult {
+                name: get_name(&schema),
+                value,
+            }),
+            Err(e) => Err(SRCError::non_retryable_with_cause(
+                e,
+                "Could not transform bytes using schema",
+            )),
+        }
+    }
+}
+
+/// Compatibility levels like the ones of the schema registry. The transitive levels are left out,
+/// since checking them requires all the previous versions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompatibilityLevel {
+    None,
+    Backward,
+    Forward,
+    Full,
+}
+
+/// Checks locally whether the reader schema, which would be the new version, is compatible with
+/// the writer schema, which would be the old version. With backward compatibility the reader
+/// should be able to read what was written with the writer schema, with forward compatibility the
+/// other way around, and with full compatibility both.
+pub fn check_avro_compatibility(
+    reader: &str,
+    writer: &str,
+    level: CompatibilityLevel,
+) -> Result<bool, SRCError> {
+    let reader = parse_schema(reader)?;
+    let writer = parse_schema(writer)?;
+    Ok(match level {
+        CompatibilityLevel::None => true,
+        CompatibilityLevel::Backward => SchemaCompatibility::can_read(&writer, &reader),
+        CompatibilityLevel::Forward => SchemaCompatibility::can_read(&reader, &writer),
+        CompatibilityLevel::Full => SchemaCompatibility::mutual_read(&writer, &reader),
+    })
+}
+
+/// Checks whether what's written with the schema of the item can be read with the schema from the
+/// schema registry, which is what consumers will use. This catches a renamed field or a changed
+/// type before the bytes are produced.
+pub(crate) fn check_item_schema(
+    item_schema: &Schema,
+    avro_schema: &AvroSchema,
+) -> Result<(), SRCError> {
+    if SchemaCompatibility::can_read(item_schema, &avro_schema.parsed) {
+        Ok(())
+    } else {
+        Err(SRCError::non_retryable_without_cause(&format!(
+            "the schema of the item is not compatible with the schema with id {}",
+            avro_schema.id
+        )))
+    }
+}
+
+fn parse_schema(schema: &str) -> Result<Schema, SRCError> {
+    match Schema::parse_str(schema) {
+        Ok(v) => Ok(v),
+        Err(e) => Err(SRCError::non_retryable_with_cause(
+            e,
+            &format!("could not parse schema {}", schema),
+        )),
+    }
+}
+
 pub(crate) fn get_name(schema: &Schema) -> Option<Name> {
     match schema {
         Schema::Record(schema) => Some(schema.name.clone()),
@@ -160,14 +587,18 @@ pub(crate) fn get_name(schema: &Schema) -> Option<Name> {
     }
 }
 
-pub fn get_supplied_schema(schema: &Schema) -> SuppliedSchema {
-    let name = match get_name(schema) {
+fn get_full_name(schema: &Schema) -> Option<String> {
+    match get_name(schema) {
         None => None,
         Some(n) => match n.namespace {
             None => Some(n.name),
             Some(ns) => Some(format!("{}.{}", ns, n.name)),
         },
-    };
+    }
+}
+
+pub fn get_supplied_schema(schema: &Schema) -> SuppliedSchema {
+    let name = get_full_name(schema);
     SuppliedSchema {
         name,
         schema_type: SchemaType::Avro,
@@ -176,15 +607,150 @@ pub fn get_supplied_schema(schema: &Schema) -> SuppliedSchema {
     }
 }
 
+fn get_record_name<T: apache_avro::AvroSchema>() -> Result<String, SRCError> {
+    match get_full_name(&T::get_schema()) {
+        Some(name) => Ok(name),
+        None => Err(SRCError::non_retryable_without_cause(
+            "the schema of the type needs to be a record to derive the record name",
+        )),
+    }
+}
+
+/// Creates a RecordNameStrategy using the fully qualified name from the avro schema of the type,
+/// so it doesn't need to be typed by hand, and can't drift from the schema.
+pub fn get_record_name_strategy<T: apache_avro::AvroSchema>(
+) -> Result<SubjectNameStrategy, SRCError> {
+    Ok(SubjectNameStrategy::RecordNameStrategy(
+        get_record_name::<T>()?,
+    ))
+}
+
+/// Creates a TopicRecordNameStrategy using the fully qualified name from the avro schema of the
+/// type, so it doesn't need to be typed by hand, and can't drift from the schema.
+pub fn get_topic_record_name_strategy<T: apache_avro::AvroSchema>(
+    topic: &str,
+) -> Result<SubjectNameStrategy, SRCError> {
+    Ok(SubjectNameStrategy::TopicRecordNameStrategy(
+        String::from(topic),
+        get_record_name::<T>()?,
+    ))
+}
+
 #[cfg(test)]
 mod tests {
     use apache_avro::types::Value;
-    use apache_avro::Schema;
+    use apache_avro::{Decimal, Schema};
 
     use test_utils::{Atype, ConfirmAccountCreation, Heartbeat};
 
-    use crate::avro_common::{values_to_bytes, AvroSchema};
+    use crate::avro_common::{
+        bytes_to_value, check_avro_compatibility, get_canonical_form, get_rabin_fingerprint,
+        get_record_name_strategy, get_topic_record_name_strategy, load_schemas_from_dir,
+        load_subject_schemas_from_dir, values_to_bytes, AvroSchema, CompatibilityLevel,
+        SingleObjectDecoder,
+    };
     use crate::error::SRCError;
+    use crate::schema_registry_common::IdEncoding;
+
+    #[test]
+    fn load_schemas_with_reference_from_dir() {
+        let dir = std::env::temp_dir().join(format!("avro-schemas-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("1.avsc"),
+            r#"{"type":"enum","name":"Atype","namespace":"nl.openweb.data","symbols":["A","B"]}"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("2.avsc"),
+            r#"{"type":"record","name":"Holder","namespace":"nl.openweb.data","fields":[{"name":"a","type":"Atype"}]}"#,
+        )
+        .unwrap();
+        std::fs::write(dir.join("README.md"), "not a schema").unwrap();
+
+        let result = load_schemas_from_dir(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+        let schemas = result.unwrap();
+
+        assert_eq!(2, schemas.len());
+        assert_eq!(1, schemas[0].id);
+        assert_eq!(2, schemas[1].id);
+        match &schemas[1].parsed {
+            Schema::Record(r) => match &r.fields[0].schema {
+                Schema::Enum(e) => assert_eq!("Atype", e.name.name),
+                s => panic!("expected an enum, got {:?}", s),
+            },
+            s => panic!("expected a record, got {:?}", s),
+        }
+    }
+
+    #[test]
+    fn load_subject_schemas_from_dir_uses_last_dot_for_id() {
+        let dir = std::env::temp_dir().join(format!("avro-subject-schemas-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("nl.openweb.data.Heartbeat.3.avsc"),
+            r#"{"type":"record","name":"Heartbeat","namespace":"nl.openweb.data","fields":[{"name":"beat","type":"long"}]}"#,
+        )
+        .unwrap();
+        std::fs::write(dir.join("heartbeat-value.avsc"), "{}").unwrap();
+
+        let result = load_subject_schemas_from_dir(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+        let schemas = result.unwrap();
+
+        assert_eq!(1, schemas.len());
+        assert_eq!("nl.openweb.data.Heartbeat", schemas[0].0);
+        assert_eq!(3, schemas[0].1.id);
+    }
+
+    #[test]
+    fn logical_types_round_trip() {
+        let parsed = Schema::parse_str(
+            r#"{"type":"record","name":"Logical","namespace":"nl.openweb.data","fields":[
+                {"name":"id","type":{"type":"string","logicalType":"uuid"}},
+                {"name":"at","type":{"type":"long","logicalType":"timestamp-millis"}},
+                {"name":"day","type":{"type":"int","logicalType":"date"}},
+                {"name":"amount","type":{"type":"bytes","logicalType":"decimal","precision":4,"scale":2}}
+            ]}"#,
+        )
+        .unwrap();
+        let schema = AvroSchema {
+            id: 5,
+            raw: "".to_string(),
+            parsed,
+        };
+        let id = "550e8400-e29b-41d4-a716-446655440000";
+
+        let bytes = values_to_bytes(
+            &schema,
+            vec![
+                ("id", Value::String(String::from(id))),
+                ("at", Value::Long(1_609_459_200_000)),
+                ("day", Value::Int(18_628)),
+                ("amount", Value::Bytes(vec![4, 210])),
+            ],
+            IdEncoding::ConfluentU32,
+        )
+        .unwrap();
+        let value = bytes_to_value(&schema.parsed, None, &bytes[5..], true).unwrap();
+
+        assert_eq!(
+            Value::Record(vec![
+                (String::from("id"), Value::Uuid(id.parse().unwrap())),
+                (
+                    String::from("at"),
+                    Value::TimestampMillis(1_609_459_200_000)
+                ),
+                (String::from("day"), Value::Date(18_628)),
+                (
+                    String::from("amount"),
+                    Value::Decimal(Decimal::from(vec![4, 210]))
+                ),
+            ]),
+            value
+        );
+    }
 
     #[test]
     fn to_bytes_no_record() {
@@ -193,7 +759,11 @@ mod tests {
             raw: "".to_string(),
             parsed: Schema::Boolean,
         };
-        let result = values_to_bytes(&schema, vec![("beat", Value::Long(3))]);
+        let result = values_to_bytes(
+            &schema,
+            vec![("beat", Value::Long(3))],
+            IdEncoding::ConfluentU32,
+        );
         assert_eq!(
             result,
             Err(SRCError::new(
@@ -211,7 +781,12 @@ mod tests {
             raw: String::from(r#"{"type":"record","name":"Name","namespace":"nl.openweb.data","fields":[{"name":"name","type":"string","avro.java.string":"String"}]}"#),
             parsed: Schema::parse_str(r#"{"type":"record","name":"Name","namespace":"nl.openweb.data","fields":[{"name":"name","type":"string","avro.java.string":"String"}]}"#).unwrap(),
         };
-        let err = values_to_bytes(&schema, vec![("beat", Value::Long(3))]).unwrap_err();
+        let err = values_to_bytes(
+            &schema,
+            vec![("beat", Value::Long(3))],
+            IdEncoding::ConfluentU32,
+        )
+        .unwrap_err();
         assert_eq!(err.error, "Could not get Avro bytes")
     }
 
@@ -226,7 +801,12 @@ mod tests {
                 r#"{"type":"record","name":"Name","namespace":"nl.openweb.data","fields":[{"name":"name","type":"string","avro.java.string":"String"}]}"#,
             ).unwrap(),
         };
-        let err = crate::avro_common::item_to_bytes(&schema, Heartbeat { beat: 3 }).unwrap_err();
+        let err = crate::avro_common::item_to_bytes(
+            &schema,
+            Heartbeat { beat: 3 },
+            IdEncoding::ConfluentU32,
+        )
+        .unwrap_err();
         assert_eq!(err.error, "Failed to resolve")
     }
 
@@ -247,7 +827,116 @@ mod tests {
             ],
             a_type: Atype::Manual,
         };
-        let err = crate::avro_common::item_to_bytes(&schema, item).unwrap_err();
+        let err =
+            crate::avro_common::item_to_bytes(&schema, item, IdEncoding::ConfluentU32).unwrap_err();
         assert_eq!(err.error, "Failed to resolve")
     }
+
+    #[test]
+    fn rabin_fingerprint_and_canonical_form() {
+        let null = Schema::parse_str(r#""null""#).unwrap();
+        assert_eq!(0x63dd24e7cc258f8a, get_rabin_fingerprint(&null));
+        let heartbeat = Schema::parse_str(r#"{"type":"record","name":"Heartbeat","namespace":"nl.openweb.data","doc":"a beat","fields":[{"name":"beat","type":"long"}]}"#).unwrap();
+        assert_eq!(
+            r#"{"name":"nl.openweb.data.Heartbeat","type":"record","fields":[{"name":"beat","type":"long"}]}"#,
+            get_canonical_form(&heartbeat)
+        );
+    }
+
+    #[test]
+    fn single_object_decode() {
+        let schema = Schema::parse_str(r#"{"type":"record","name":"Heartbeat","namespace":"nl.openweb.data","fields":[{"name":"beat","type":"long"}]}"#).unwrap();
+        let mut writer =
+            apache_avro::GenericSingleObjectWriter::new_with_capacity(&schema, 16).unwrap();
+        let mut bytes = Vec::new();
+        writer
+            .write_value(
+                Value::Record(vec![("beat".to_string(), Value::Long(3))]),
+                &mut bytes,
+            )
+            .unwrap();
+
+        let decoder = SingleObjectDecoder::new();
+        let error = decoder.decode(Some(&bytes)).unwrap_err();
+        assert!(error.error.starts_with("No schema added for fingerprint"));
+
+        decoder.add_schema(schema);
+        let result = decoder.decode(Some(&bytes)).unwrap();
+        assert_eq!(
+            result.value,
+            Value::Record(vec![("beat".to_string(), Value::Long(3))])
+        );
+        assert_eq!(result.name.unwrap().name, "Heartbeat");
+    }
+
+    #[test]
+    fn single_object_decode_invalid_bytes() {
+        let decoder = SingleObjectDecoder::new();
+        let error = decoder.decode(Some(&[0, 0, 0, 0, 1, 6])).unwrap_err();
+        assert_eq!(error.error, "Invalid bytes [0, 0, 0, 0, 1, 6]");
+    }
+
+    #[test]
+    fn compatibility_added_field_with_default() {
+        let old =
+            r#"{"type":"record","name":"Heartbeat","fields":[{"name":"beat","type":"long"}]}"#;
+        let new = r#"{"type":"record","name":"Heartbeat","fields":[{"name":"beat","type":"long"},{"name":"note","type":"string","default":""}]}"#;
+        assert_eq!(
+            Ok(true),
+            check_avro_compatibility(new, old, CompatibilityLevel::Backward)
+        );
+        assert_eq!(
+            Ok(true),
+            check_avro_compatibility(new, old, CompatibilityLevel::Full)
+        );
+    }
+
+    #[test]
+    fn compatibility_changed_type() {
+        let old =
+            r#"{"type":"record","name":"Heartbeat","fields":[{"name":"beat","type":"long"}]}"#;
+        let new =
+            r#"{"type":"record","name":"Heartbeat","fields":[{"name":"beat","type":"string"}]}"#;
+        assert_eq!(
+            Ok(false),
+            check_avro_compatibility(new, old, CompatibilityLevel::Backward)
+        );
+        assert_eq!(
+            Ok(false),
+            check_avro_compatibility(new, old, CompatibilityLevel::Forward)
+        );
+        assert_eq!(
+            Ok(true),
+            check_avro_compatibility(new, old, CompatibilityLevel::None)
+        );
+        assert!(check_avro_compatibility("{", old, CompatibilityLevel::Backward).is_err());
+    }
+
+    struct DerivedHeartbeat;
+
+    impl apache_avro::AvroSchema for DerivedHeartbeat {
+        fn get_schema() -> Schema {
+            Schema::parse_str(r#"{"type":"record","name":"Heartbeat","namespace":"nl.openweb.data","fields":[{"name":"beat","type":"long"}]}"#).unwrap()
+        }
+    }
+
+    struct DerivedString;
+
+    impl apache_avro::AvroSchema for DerivedString {
+        fn get_schema() -> Schema {
+            Schema::String
+        }
+    }
+
+    #[test]
+    fn record_name_strategies_from_type() {
+        let strategy = get_record_name_strategy::<DerivedHeartbeat>().unwrap();
+        assert_eq!("nl.openweb.data.Heartbeat", strategy.get_subject().unwrap());
+        let strategy = get_topic_record_name_strategy::<DerivedHeartbeat>("beats").unwrap();
+        assert_eq!(
+            "beats-nl.openweb.data.Heartbeat",
+            strategy.get_subject().unwrap()
+        );
+        assert!(get_topic_record_name_strategy::<DerivedString>("beats").is_err());
+    }
 }
@@ -22,27 +22,31 @@
 //!
 //! [avro-rs]: https://crates.io/crates/avro-rs
 
-use std::io::Cursor;
+use std::collections::HashMap;
+use std::path::Path;
 use std::sync::Arc;
 
 use apache_avro::types::Value;
-use apache_avro::{from_avro_datum, Schema};
+use apache_avro::Schema;
 use dashmap::mapref::entry::Entry;
 use dashmap::DashMap;
+use serde::de::DeserializeOwned;
 use serde::ser::Serialize;
 use serde_json::Value as JsonValue;
 
 use crate::avro_common::{
-    get_name, item_to_bytes, replace_reference, values_to_bytes, AvroSchema, DecodeResult,
-    DecodeResultWithSchema,
+    bytes_to_value, check_item_schema, check_payload_len, check_reference_cycle, get_name,
+    inline_references, item_to_bytes, load_schemas_from_dir, load_subject_schemas_from_dir,
+    value_to_item, values_to_bytes, AvroSchema, DecodeResult, DecodeResultWithSchema,
 };
 use crate::blocking::schema_registry::{
     get_referenced_schema, get_schema_by_id_and_type, get_schema_by_subject, SrSettings,
 };
 use crate::error::SRCError;
 use crate::schema_registry_common::{
-    get_bytes_result, BytesResult, RegisteredReference, RegisteredSchema, SchemaType,
-    SubjectNameStrategy,
+    get_bytes_result_with_id_encoding, get_bytes_result_without_magic_byte, BytesResult,
+    IdEncoding, RegisteredReference, RegisteredSchema, SchemaType, SubjectNameStrategy,
+    SubjectNamer,
 };
 
 /// A decoder used to transform bytes to a Value object
@@ -80,6 +84,10 @@ use crate::schema_registry_common::{
 pub struct AvroDecoder {
     sr_settings: SrSettings,
     cache: DashMap<u32, Result<Arc<AvroSchema>, SRCError>>,
+    strict_length: bool,
+    max_payload_len: Option<usize>,
+    skip_magic_byte: bool,
+    id_encoding: IdEncoding,
 }
 
 impl AvroDecoder {
@@ -92,6 +100,51 @@ impl AvroDecoder {
         AvroDecoder {
             sr_settings,
             cache: DashMap::new(),
+            strict_length: false,
+            max_payload_len: None,
+            skip_magic_byte: false,
+            id_encoding: IdEncoding::ConfluentU32,
+        }
+    }
+    /// When set to true decoding fails when bytes remain after the value is read, by default any
+    /// trailing bytes are ignored.
+    pub fn set_strict_length(&mut self, strict_length: bool) {
+        self.strict_length = strict_length;
+    }
+    /// Sets the maximum length of the bytes to decode, without the magic byte and id. Longer bytes
+    /// fail to decode before the schema is fetched, by default there is no maximum.
+    pub fn set_max_payload_len(&mut self, max_payload_len: usize) {
+        self.max_payload_len = Some(max_payload_len);
+    }
+    /// When set to true the bytes are expected to start with the 4 byte id, without the magic byte,
+    /// as some legacy producers do. This is not the standard framing, so it should only be set for
+    /// topics that only contain such bytes.
+    pub fn set_skip_magic_byte(&mut self, skip_magic_byte: bool) {
+        self.skip_magic_byte = skip_magic_byte;
+    }
+    /// Sets how the id is encoded after the magic byte, by default the 4 byte Confluent encoding.
+    pub fn set_id_encoding(&mut self, id_encoding: IdEncoding) {
+        self.id_encoding = id_encoding;
+    }
+    /// Adds the schemas read from the `.avsc` files in the directory to the cache, so decoding
+    /// bytes with one of those ids doesn't need the schema registry. See
+    /// [load_schemas_from_dir](crate::avro_common::load_schemas_from_dir) for the file layout.
+    /// Returns the number of schemas added. For encoding, see
+    /// [AvroEncoder::add_fixed_schemas_from_dir].
+    pub fn add_schemas_from_dir(&self, dir: &Path) -> Result<usize, SRCError> {
+        let schemas = load_schemas_from_dir(dir)?;
+        let count = schemas.len();
+        for schema in schemas {
+            self.cache.insert(schema.id, Ok(Arc::new(schema)));
+        }
+        Ok(count)
+    }
+    /// Splits the bytes into the id and the rest, depending on whether the magic byte is skipped.
+    fn bytes_result(&self, bytes: Option<&[u8]>) -> BytesResult {
+        if self.skip_magic_byte {
+            get_bytes_result_without_magic_byte(bytes)
+        } else {
+            get_bytes_result_with_id_encoding(bytes, self.id_encoding)
         }
     }
     /// Remove al the errors from the cache, you might need to/want to run this when a recoverable
@@ -117,7 +170,7 @@ impl AvroDecoder {
     ///
     /// let heartbeat = decoder.decode(Some(&bytes));
     ///
-    /// assert_eq!(heartbeat, Err(SRCError::new("Could not get raw schema from response", None, false).into_cache()));
+    /// assert_eq!(heartbeat, Err(SRCError::not_found("schema registry responded with status 404 Not Found", Some(String::from(r#"{"error_code":40403,"message":"Schema not found"}"#))).into_cache()));
     ///
     /// let _m = server .mock("GET", "/schemas/ids/2?deleted=true")
     ///     .with_status(200)
@@ -126,7 +179,7 @@ impl AvroDecoder {
     ///     .create();
     ///
     /// let heartbeat = decoder.decode(Some(&bytes));
-    /// assert_eq!(heartbeat, Err(SRCError::new("Could not get raw schema from response", None, false).into_cache()));
+    /// assert_eq!(heartbeat, Err(SRCError::not_found("schema registry responded with status 404 Not Found", Some(String::from(r#"{"error_code":40403,"message":"Schema not found"}"#))).into_cache()));
     ///
     /// decoder.remove_errors_from_cache();
     ///
@@ -157,7 +210,7 @@ impl AvroDecoder {
     /// }
     /// ```
     pub fn decode(&self, bytes: Option<&[u8]>) -> Result<DecodeResult, SRCError> {
-        match get_bytes_result(bytes) {
+        match self.bytes_result(bytes) {
             BytesResult::Null => Ok(DecodeResult {
                 name: None,
                 value: Value::Null,
@@ -172,21 +225,12 @@ impl AvroDecoder {
     /// The actual deserialization trying to get the id from the bytes to retrieve the schema, and
     /// using a reader transforms the bytes to a value.
     fn deserialize(&self, id: u32, bytes: &[u8]) -> Result<DecodeResult, SRCError> {
-        let schema = self.schema(id);
-        let mut reader = Cursor::new(bytes);
-        match schema {
-            Ok(s) => match from_avro_datum(&s.parsed, &mut reader, None) {
-                Ok(v) => Ok(DecodeResult {
-                    name: get_name(&s.parsed),
-                    value: v,
-                }),
-                Err(e) => Err(SRCError::non_retryable_with_cause(
-                    e,
-                    "Could not transform bytes using schema",
-                )),
-            },
-            Err(e) => Err(e),
-        }
+        check_payload_len(bytes, self.max_payload_len)?;
+        let schema = self.schema(id)?;
+        Ok(DecodeResult {
+            name: get_name(&schema.parsed),
+            value: bytes_to_value(&schema.parsed, None, bytes, self.strict_length)?,
+        })
     }
     /// Decodes bytes into a value.
     /// The choice to use Option<&[u8]> as type us made so it plays nice with the BorrowedMessage
@@ -197,7 +241,7 @@ impl AvroDecoder {
         &self,
         bytes: Option<&[u8]>,
     ) -> Result<Option<DecodeResultWithSchema>, SRCError> {
-        match get_bytes_result(bytes) {
+        match self.bytes_result(bytes) {
             BytesResult::Null => Ok(None),
             BytesResult::Valid(id, bytes) => match self.deserialize_with_schema(id, &bytes) {
                 Ok(v) => Ok(Some(v)),
@@ -209,6 +253,141 @@ impl AvroDecoder {
             ))),
         }
     }
+    /// Decodes bytes into a value like decode, but resolves the value against the reader schema.
+    /// This makes it possible to read data written with an older or newer version of the schema,
+    /// for example fields added with a default get the default value. The name is taken from the
+    /// reader schema.
+    pub fn decode_with_reader_schema(
+        &self,
+        bytes: Option<&[u8]>,
+        reader_schema: &Schema,
+    ) -> Result<DecodeResult, SRCError> {
+        match self.bytes_result(bytes) {
+            BytesResult::Null => Ok(DecodeResult {
+                name: None,
+                value: Value::Null,
+            }),
+            BytesResult::Valid(id, bytes) => {
+                check_payload_len(&bytes, self.max_payload_len)?;
+                let schema = self.schema(id)?;
+                Ok(DecodeResult {
+                    name: get_name(reader_schema),
+                    value: bytes_to_value(
+                        &schema.parsed,
+                        Some(reader_schema),
+                        &bytes,
+                        self.strict_length,
+                    )?,
+                })
+            }
+            BytesResult::Invalid(bytes) => Err(SRCError::non_retryable_without_cause(&format!(
+                "Invalid bytes {:?}",
+                bytes
+            ))),
+        }
+    }
+    /// Decodes bytes that might already have the magic byte and id removed. When the bytes start
+    /// with the magic byte, and are long enough to contain an id, they are decoded like with decode.
+    /// Otherwise they are decoded using the schema with the fallback id, which is required in that
+    /// case. Since unframed bytes might also start with a zero, this should only be used when
+    /// unframed values never do, for example when they are records starting with a non zero field.
+    pub fn decode_auto_framed(
+        &self,
+        bytes: Option<&[u8]>,
+        fallback_id: Option<u32>,
+    ) -> Result<Option<DecodeResult>, SRCError> {
+        match (self.bytes_result(bytes), fallback_id) {
+            (BytesResult::Null, _) => Ok(None),
+            (BytesResult::Valid(id, bytes), _) => Ok(Some(self.deserialize(id, &bytes)?)),
+            (BytesResult::Invalid(bytes), Some(id)) => Ok(Some(self.deserialize(id, &bytes)?)),
+            (BytesResult::Invalid(bytes), None) => {
+                Err(SRCError::non_retryable_without_cause(&format!(
+                    "Bytes {:?} are not framed, and no fallback id was supplied",
+                    bytes
+                )))
+            }
+        }
+    }
+    /// Decodes bytes without magic byte and id, using the schema with the given id. This is useful
+    /// when the id is transported separately, for example in a Kafka header. Returns none when the
+    /// bytes are none.
+    pub fn decode_unframed(
+        &self,
+        id: u32,
+        bytes: Option<&[u8]>,
+    ) -> Result<Option<DecodeResult>, SRCError> {
+        match bytes {
+            None => Ok(None),
+            Some(bytes) => Ok(Some(self.deserialize(id, bytes)?)),
+        }
+    }
+    /// Decodes many bytes, like decode, returning the results in the same order. The schema for
+    /// each distinct id is only looked up once for the whole batch, which saves work when draining
+    /// a partition where most of the values share the same schema.
+    pub fn decode_batch(&self, items: &[Option<&[u8]>]) -> Vec<Result<DecodeResult, SRCError>> {
+        let mut schemas: HashMap<u32, Result<Arc<AvroSchema>, SRCError>> = HashMap::new();
+        let mut results = Vec::with_capacity(items.len());
+        for bytes in items {
+            let result = match self.bytes_result(*bytes) {
+                BytesResult::Null => Ok(DecodeResult {
+                    name: None,
+                    value: Value::Null,
+                }),
+                BytesResult::Valid(id, bytes) => schemas
+                    .entry(id)
+                    .or_insert_with(|| self.schema(id))
+                    .clone()
+                    .and_then(|schema| self.deserialize_with(&schema, &bytes)),
+                BytesResult::Invalid(bytes) => Err(SRCError::non_retryable_without_cause(
+                    &format!("Invalid bytes {:?}", bytes),
+                )),
+            };
+            results.push(result);
+        }
+        results
+    }
+    /// Deserializes the bytes with an already retrieved schema.
+    fn deserialize_with(
+        &self,
+        schema: &AvroSchema,
+        bytes: &[u8],
+    ) -> Result<DecodeResult, SRCError> {
+        check_payload_len(bytes, self.max_payload_len)?;
+        Ok(DecodeResult {
+            name: get_name(&schema.parsed),
+            value: bytes_to_value(&schema.parsed, None, bytes, self.strict_length)?,
+        })
+    }
+    /// Decodes bytes into an item, for example a struct generated from the schema, using serde.
+    /// Returns none when the bytes are none or empty, so tombstones on compacted topics can be
+    /// handled without inspecting the bytes first.
+    pub fn decode_struct<T: DeserializeOwned>(
+        &self,
+        bytes: Option<&[u8]>,
+    ) -> Result<Option<T>, SRCError> {
+        match bytes {
+            None | Some([]) => Ok(None),
+            Some(_) => {
+                let result = self.decode(bytes)?;
+                Ok(Some(value_to_item(&result.value)?))
+            }
+        }
+    }
+    /// Decodes bytes into a value, like decode, but also returns the id of the schema that was used,
+    /// which is read from the bytes. Returns none when the bytes are none.
+    pub fn decode_with_id(
+        &self,
+        bytes: Option<&[u8]>,
+    ) -> Result<Option<(u32, DecodeResult)>, SRCError> {
+        match self.bytes_result(bytes) {
+            BytesResult::Null => Ok(None),
+            BytesResult::Valid(id, bytes) => Ok(Some((id, self.deserialize(id, &bytes)?))),
+            BytesResult::Invalid(bytes) => Err(SRCError::non_retryable_without_cause(&format!(
+                "Invalid bytes {:?}",
+                bytes
+            ))),
+        }
+    }
     /// The actual deserialization trying to get the id from the bytes to retrieve the schema, and
     /// using a reader transforms the bytes to a value.
     fn deserialize_with_schema(
@@ -216,29 +395,28 @@ impl AvroDecoder {
         id: u32,
         bytes: &[u8],
     ) -> Result<DecodeResultWithSchema, SRCError> {
-        let optional_schema = self.schema(id);
-        let mut reader = Cursor::new(bytes);
-        match optional_schema {
-            Ok(schema) => match from_avro_datum(&schema.parsed, &mut reader, None) {
-                Ok(value) => Ok(DecodeResultWithSchema {
-                    name: get_name(&schema.parsed),
-                    value,
-                    schema,
-                }),
-                Err(e) => Err(SRCError::non_retryable_with_cause(
-                    e,
-                    "Could not transform bytes using schema",
-                )),
-            },
-            Err(e) => Err(e),
-        }
+        check_payload_len(bytes, self.max_payload_len)?;
+        let schema = self.schema(id)?;
+        Ok(DecodeResultWithSchema {
+            name: get_name(&schema.parsed),
+            value: bytes_to_value(&schema.parsed, None, bytes, self.strict_length)?,
+            schema,
+        })
     }
 
     fn schema(&self, id: u32) -> Result<Arc<AvroSchema>, SRCError> {
         let sr_settings = &self.sr_settings;
         match self.cache.entry(id) {
-            Entry::Occupied(e) => e.get().clone(),
+            Entry::Occupied(e) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(id, "schema cache hit");
+                self.sr_settings.observe_cache_hit();
+                e.get().clone()
+            }
             Entry::Vacant(e) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(id, "schema cache miss");
+                self.sr_settings.observe_cache_miss();
                 let v = match get_schema_by_id_and_type(id, sr_settings, SchemaType::Avro) {
                     Ok(registered_schema) => to_avro_schema(sr_settings, registered_schema),
                     Err(e) => Err(e.into_cache()),
@@ -298,6 +476,7 @@ impl AvroDecoder {
 pub struct AvroEncoder {
     sr_settings: SrSettings,
     cache: DashMap<String, Result<Arc<AvroSchema>, SRCError>>,
+    id_encoding: IdEncoding,
 }
 
 impl AvroEncoder {
@@ -338,7 +517,39 @@ impl AvroEncoder {
         AvroEncoder {
             sr_settings,
             cache: DashMap::new(),
+            id_encoding: IdEncoding::ConfluentU32,
+        }
+    }
+    /// Sets how the id is encoded after the magic byte, by default the 4 byte Confluent encoding.
+    pub fn set_id_encoding(&mut self, id_encoding: IdEncoding) {
+        self.id_encoding = id_encoding;
+    }
+    /// Uses the schema with the id for the subject, without getting it from the schema registry.
+    /// This way a producer that only writes one schema doesn't depend on the schema registry being
+    /// available. The id is put in the bytes as is, so it should be the id the schema registry
+    /// has for the schema. References are not supported.
+    pub fn add_fixed_schema(&self, subject: &str, id: u32, schema: &str) -> Result<(), SRCError> {
+        let parsed = Schema::parse_str(schema).map_err(|e| {
+            SRCError::non_retryable_with_cause(e, &format!("could not parse schema {}", schema))
+        })?;
+        let avro_schema = Arc::new(AvroSchema {
+            id,
+            raw: String::from(schema),
+            parsed,
+        });
+        self.cache.insert(String::from(subject), Ok(avro_schema));
+        Ok(())
+    }
+    /// Uses the schemas read from the `.avsc` files in the directory for their subjects, like
+    /// [AvroEncoder::add_fixed_schema] does for a single schema. See
+    /// [load_subject_schemas_from_dir] for the file layout. Returns the number of schemas added.
+    pub fn add_fixed_schemas_from_dir(&self, dir: &Path) -> Result<usize, SRCError> {
+        let schemas = load_subject_schemas_from_dir(dir)?;
+        let count = schemas.len();
+        for (subject, schema) in schemas {
+            self.cache.insert(subject, Ok(Arc::new(schema)));
         }
+        Ok(count)
     }
     /// Remove al the errors from the cache, you might need to/want to run this when a recoverable
     /// error is met. Errors are also cashed to prevent trying to get schema's that either don't
@@ -363,7 +574,7 @@ impl AvroEncoder {
     ///     .create();
     ///
     /// let bytes = encoder.encode(vec![("beat", Value::Long(3))], &strategy);
-    /// assert_eq!(bytes, Err(SRCError::new("Could not get id from response", None, false).into_cache()));
+    /// assert_eq!(bytes, Err(SRCError::not_found("schema registry responded with status 404 Not Found", Some(String::from(r#"{"error_code":40403,"message":"Schema not found"}"#))).into_cache()));
     ///
     /// let _m = server .mock("GET", "/subjects/nl.openweb.data.Heartbeat/versions/latest")
     ///     .with_status(200)
@@ -372,7 +583,7 @@ impl AvroEncoder {
     ///     .create();
     ///
     /// let bytes = encoder.encode(vec![("beat", Value::Long(3))], &strategy);
-    /// assert_eq!(bytes, Err(SRCError::new("Could not get id from response", None, false).into_cache()));
+    /// assert_eq!(bytes, Err(SRCError::not_found("schema registry responded with status 404 Not Found", Some(String::from(r#"{"error_code":40403,"message":"Schema not found"}"#))).into_cache()));
     ///
     /// encoder.remove_errors_from_cache();
     ///
@@ -414,7 +625,7 @@ impl AvroEncoder {
     ) -> Result<Vec<u8>, SRCError> {
         let key = subject_name_strategy.get_subject()?;
         match self.get_schema_and_id(key, subject_name_strategy) {
-            Ok(avro_schema) => values_to_bytes(&avro_schema, values),
+            Ok(avro_schema) => values_to_bytes(&avro_schema, values, self.id_encoding),
             Err(e) => Err(e),
         }
     }
@@ -471,11 +682,56 @@ impl AvroEncoder {
     ) -> Result<Vec<u8>, SRCError> {
         let key = subject_name_strategy.get_subject()?;
         match self.get_schema_and_id(key, subject_name_strategy) {
-            Ok(avro_schema) => item_to_bytes(&avro_schema, item),
+            Ok(avro_schema) => item_to_bytes(&avro_schema, item, self.id_encoding),
             Err(e) => Err(e),
         }
     }
 
+    /// Like encode_struct, but with an optional item. When the item is none an empty vector is
+    /// returned without fetching the schema, which is produced as a tombstone, for example to
+    /// delete a key from a compacted topic.
+    pub fn encode_struct_optional(
+        &self,
+        item: Option<impl Serialize>,
+        subject_name_strategy: &SubjectNameStrategy,
+    ) -> Result<Vec<u8>, SRCError> {
+        match item {
+            None => Ok(Vec::new()),
+            Some(item) => self.encode_struct(item, subject_name_strategy),
+        }
+    }
+
+    /// Like encode_struct, but first checking whether the schema derived for the item, for example
+    /// with `#[derive(AvroSchema)]`, is compatible with the schema from the schema registry. This
+    /// catches a renamed field or a changed type, which could otherwise give bytes that can't be
+    /// read with the schema. The check is done for each call, so it's best used in tests, or for
+    /// low volume topics.
+    pub fn encode_struct_verified<T: Serialize + apache_avro::AvroSchema>(
+        &self,
+        item: T,
+        subject_name_strategy: &SubjectNameStrategy,
+    ) -> Result<Vec<u8>, SRCError> {
+        let key = subject_name_strategy.get_subject()?;
+        let avro_schema = self.get_schema_and_id(key, subject_name_strategy)?;
+        check_item_schema(&T::get_schema(), &avro_schema)?;
+        item_to_bytes(&avro_schema, item, self.id_encoding)
+    }
+
+    /// Like encode_struct, but using the subject computed by the SubjectNamer, for the topic and
+    /// the fully qualified name of the record. The latest schema registered for the subject is
+    /// used.
+    pub fn encode_struct_with_namer(
+        &self,
+        item: impl Serialize,
+        subject_namer: &dyn SubjectNamer,
+        topic: &str,
+        record: &str,
+        is_key: bool,
+    ) -> Result<Vec<u8>, SRCError> {
+        let subject = subject_namer.subject(topic, record, is_key);
+        self.encode_struct(item, &SubjectNameStrategy::RecordNameStrategy(subject))
+    }
+
     fn get_schema_and_id(
         &self,
         key: String,
@@ -483,8 +739,16 @@ impl AvroEncoder {
     ) -> Result<Arc<AvroSchema>, SRCError> {
         let sr_settings = &self.sr_settings;
         match self.cache.entry(key) {
-            Entry::Occupied(e) => e.get().clone(),
+            Entry::Occupied(e) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(subject = e.key(), "schema cache hit");
+                self.sr_settings.observe_cache_hit();
+                e.get().clone()
+            }
             Entry::Vacant(e) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(subject = e.key(), "schema cache miss");
+                self.sr_settings.observe_cache_miss();
                 let v = match get_schema_by_subject(sr_settings, subject_name_strategy) {
                     Ok(registered_schema) => to_avro_schema(sr_settings, registered_schema),
                     Err(e) => Err(e.into_cache()),
@@ -500,8 +764,26 @@ fn add_references(
     json_value: JsonValue,
     references: &[RegisteredReference],
 ) -> Result<JsonValue, SRCError> {
-    let mut new_value = json_value;
+    let mut collected = Vec::new();
+    collect_references(sr_settings, references, &[], &mut collected)?;
+    let children: Vec<JsonValue> = collected.into_iter().map(|(_, v)| v).collect();
+    Ok(inline_references(json_value, &children))
+}
+
+/// Collects the schemas of the references, and of their references, each subject and version only
+/// once.
+fn collect_references(
+    sr_settings: &SrSettings,
+    references: &[RegisteredReference],
+    resolving: &[(String, u32)],
+    collected: &mut Vec<((String, u32), JsonValue)>,
+) -> Result<(), SRCError> {
     for r in references.iter() {
+        check_reference_cycle(resolving, r)?;
+        let key = (r.subject.clone(), r.version);
+        if collected.iter().any(|(k, _)| *k == key) {
+            continue;
+        }
         let registered_schema = match get_referenced_schema(sr_settings, r) {
             Ok(v) => v,
             Err(e) => {
@@ -520,13 +802,12 @@ fn add_references(
                 ));
             }
         };
-        new_value = replace_reference(new_value, child);
-        new_value = match add_references(sr_settings, new_value, &registered_schema.references) {
-            Ok(v) => v,
-            Err(e) => return Err(e),
-        }
+        collected.push((key.clone(), child));
+        let mut path = resolving.to_vec();
+        path.push(key);
+        collect_references(sr_settings, &registered_schema.references, &path, collected)?;
     }
-    Ok(new_value)
+    Ok(())
 }
 
 fn to_avro_schema(
@@ -575,9 +856,12 @@ mod tests {
     use apache_avro::from_value;
 
     use crate::avro_common::get_supplied_schema;
-    use crate::schema_registry_common::SuppliedSchema;
+    use crate::schema_registry_common::{ErrorClassifier, Observer, SuppliedSchema};
 
     use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+    use std::time::Duration;
     use test_utils::Heartbeat;
 
     #[test]
@@ -585,7 +869,7 @@ mod tests {
         let sr_settings = SrSettings::new(String::from("http://127.0.0.1:1234"));
         let decoder = AvroDecoder::new(sr_settings);
         assert_eq!(
-            "AvroDecoder { sr_settings: SrSettings { urls: [\"http://127.0.0.1:1234\"], client: Client, authorization: None }, cache: {} }"
+            "AvroDecoder { sr_settings: SrSettings { urls: [\"http://127.0.0.1:1234\"], authorization: None }, cache: {}, strict_length: false, max_payload_len: None, skip_magic_byte: false, id_encoding: ConfluentU32 }"
                 .to_owned(),
             format!("{:?}", decoder)
         )
@@ -669,6 +953,360 @@ mod tests {
         assert_eq!(item.beat, 3i64);
     }
 
+    #[test]
+    fn test_decode_trailing_bytes() {
+        let mut server = mockito::Server::new();
+        let _m = server.mock("GET", "/schemas/ids/1?deleted=true")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"schema":"{\"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
+            .create();
+
+        let bytes = [0, 0, 0, 0, 1, 6, 0, 0];
+        let sr_settings = SrSettings::new(server.url());
+        let mut decoder = AvroDecoder::new(sr_settings);
+        let heartbeat = decoder.decode(Some(&bytes)).unwrap().value;
+        assert_eq!(
+            heartbeat,
+            Value::Record(vec![("beat".to_string(), Value::Long(3))])
+        );
+
+        decoder.set_strict_length(true);
+        let error = decoder.decode(Some(&bytes)).unwrap_err();
+        assert_eq!(
+            error.error,
+            "2 bytes remained after transforming the bytes using schema"
+        );
+        assert!(!error.retriable);
+        let heartbeat = decoder.decode(Some(&bytes[..6])).unwrap().value;
+        assert_eq!(
+            heartbeat,
+            Value::Record(vec![("beat".to_string(), Value::Long(3))])
+        );
+    }
+
+    #[test]
+    fn test_decode_skip_magic_byte() {
+        let mut server = mockito::Server::new();
+        let _m = server.mock("GET", "/schemas/ids/1?deleted=true")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"schema":"{\"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
+            .create();
+
+        let sr_settings = SrSettings::new(server.url());
+        let mut decoder = AvroDecoder::new(sr_settings);
+        decoder.set_skip_magic_byte(true);
+
+        let heartbeat = decoder.decode(Some(&[0, 0, 0, 1, 6])).unwrap().value;
+        assert_eq!(
+            heartbeat,
+            Value::Record(vec![("beat".to_string(), Value::Long(3))])
+        );
+    }
+
+    #[test]
+    fn test_decode_with_max_payload_len() {
+        let sr_settings = SrSettings::new(String::from("http://127.0.0.1:1234"));
+        let mut decoder = AvroDecoder::new(sr_settings);
+        decoder.set_max_payload_len(1);
+
+        let error = decoder.decode(Some(&[0, 0, 0, 0, 1, 6, 0])).unwrap_err();
+        assert_eq!(
+            error.error,
+            "payload of 2 bytes is larger than the maximum of 1 bytes"
+        );
+        assert!(!error.retriable);
+    }
+
+    #[test]
+    fn test_decode_with_reader_schema() {
+        let mut server = mockito::Server::new();
+        let _m = server.mock("GET", "/schemas/ids/1?deleted=true")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"schema":"{\"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
+            .create();
+
+        let reader_schema = Schema::parse_str(r#"{"type":"record","name":"Heartbeat","namespace":"nl.openweb.data","fields":[{"name":"beat","type":"long"},{"name":"source","type":"string","default":"unknown"}]}"#).unwrap();
+        let sr_settings = SrSettings::new(server.url());
+        let decoder = AvroDecoder::new(sr_settings);
+
+        let result = decoder
+            .decode_with_reader_schema(Some(&[0, 0, 0, 0, 1, 6]), &reader_schema)
+            .unwrap();
+        assert_eq!(
+            result.value,
+            Value::Record(vec![
+                ("beat".to_string(), Value::Long(3)),
+                ("source".to_string(), Value::String("unknown".to_string())),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_decode_struct() {
+        let mut server = mockito::Server::new();
+        let _m = server.mock("GET", "/schemas/ids/1?deleted=true")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"schema":"{\"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
+            .create();
+
+        let sr_settings = SrSettings::new(server.url());
+        let decoder = AvroDecoder::new(sr_settings);
+
+        let item = decoder.decode_struct::<Heartbeat>(Some(&[0, 0, 0, 0, 1, 6]));
+        assert_eq!(item.unwrap().unwrap().beat, 3);
+        assert!(decoder.decode_struct::<Heartbeat>(None).unwrap().is_none());
+        assert!(decoder
+            .decode_struct::<Heartbeat>(Some(&[]))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_decode_batch() {
+        let mut server = mockito::Server::new();
+        let _m = server.mock("GET", "/schemas/ids/1?deleted=true")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"schema":"{\"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
+            .expect(1)
+            .create();
+
+        let sr_settings = SrSettings::new(server.url());
+        let decoder = AvroDecoder::new(sr_settings);
+        let first: &[u8] = &[0, 0, 0, 0, 1, 6];
+        let second: &[u8] = &[0, 0, 0, 0, 1, 8];
+        let invalid: &[u8] = &[1, 6];
+
+        let results = decoder.decode_batch(&[Some(first), None, Some(invalid), Some(second)]);
+
+        _m.assert();
+        assert_eq!(results.len(), 4);
+        assert_eq!(
+            results[0].as_ref().unwrap().value,
+            Value::Record(vec![("beat".to_string(), Value::Long(3))])
+        );
+        assert_eq!(results[1].as_ref().unwrap().value, Value::Null);
+        assert!(results[2].is_err());
+        assert_eq!(
+            results[3].as_ref().unwrap().value,
+            Value::Record(vec![("beat".to_string(), Value::Long(4))])
+        );
+    }
+
+    #[test]
+    fn test_decode_unframed() {
+        let mut server = mockito::Server::new();
+        let _m = server.mock("GET", "/schemas/ids/1?deleted=true")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"schema":"{\"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
+            .create();
+
+        let sr_settings = SrSettings::new(server.url());
+        let decoder = AvroDecoder::new(sr_settings);
+
+        let result = decoder.decode_unframed(1, Some(&[6])).unwrap().unwrap();
+        assert_eq!(
+            result.value,
+            Value::Record(vec![("beat".to_string(), Value::Long(3))])
+        );
+        assert_eq!(decoder.decode_unframed(1, None), Ok(None));
+    }
+
+    #[test]
+    fn test_decode_with_schemas_from_dir() {
+        let server = mockito::Server::new();
+        let dir = std::env::temp_dir().join(format!("blocking-avro-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("1.avsc"),
+            r#"{"type":"record","name":"Heartbeat","namespace":"nl.openweb.data","fields":[{"name":"beat","type":"long"}]}"#,
+        )
+        .unwrap();
+
+        let sr_settings = SrSettings::new(server.url());
+        let decoder = AvroDecoder::new(sr_settings);
+        let added = decoder.add_schemas_from_dir(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(added, Ok(1));
+
+        let result = decoder.decode(Some(&[0, 0, 0, 0, 1, 6])).unwrap();
+        assert_eq!(
+            result.value,
+            Value::Record(vec![("beat".to_string(), Value::Long(3))])
+        );
+    }
+
+    #[test]
+    fn test_decode_auto_framed() {
+        let mut server = mockito::Server::new();
+        let _m = server.mock("GET", "/schemas/ids/1?deleted=true")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"schema":"{\"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
+            .create();
+
+        let sr_settings = SrSettings::new(server.url());
+        let decoder = AvroDecoder::new(sr_settings);
+        let expected = Value::Record(vec![("beat".to_string(), Value::Long(3))]);
+
+        let framed = decoder
+            .decode_auto_framed(Some(&[0, 0, 0, 0, 1, 6]), None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(framed.value, expected);
+        let unframed = decoder
+            .decode_auto_framed(Some(&[6]), Some(1))
+            .unwrap()
+            .unwrap();
+        assert_eq!(unframed.value, expected);
+        let error = decoder.decode_auto_framed(Some(&[6]), None).unwrap_err();
+        assert_eq!(
+            error.error,
+            "Bytes [6] are not framed, and no fallback id was supplied"
+        );
+        assert_eq!(decoder.decode_auto_framed(None, Some(1)), Ok(None));
+    }
+
+    #[test]
+    fn test_decode_auto_framed_apicurio_id_encoding() {
+        let mut server = mockito::Server::new();
+        let _m = server.mock("GET", "/schemas/ids/1?deleted=true")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"schema":"{\"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
+            .create();
+
+        let mut decoder = AvroDecoder::new(SrSettings::new(server.url()));
+        decoder.set_id_encoding(IdEncoding::ApicurioU64);
+
+        let framed = decoder
+            .decode_auto_framed(Some(&[0, 0, 0, 0, 0, 0, 0, 0, 1, 6]), None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            framed.value,
+            Value::Record(vec![("beat".to_string(), Value::Long(3))])
+        );
+    }
+
+    #[derive(Debug, Default)]
+    struct CountingObserver {
+        hits: AtomicUsize,
+        misses: AtomicUsize,
+        fetches: Mutex<Vec<bool>>,
+    }
+
+    impl Observer for CountingObserver {
+        fn on_cache_hit(&self) {
+            self.hits.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn on_cache_miss(&self) {
+            self.misses.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn on_fetch(&self, _duration: Duration, succeeded: bool) {
+            self.fetches.lock().unwrap().push(succeeded);
+        }
+    }
+
+    #[test]
+    fn test_decode_notifies_observer() {
+        let mut server = mockito::Server::new();
+        let _m = server.mock("GET", "/schemas/ids/1?deleted=true")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"schema":"{\"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
+            .create();
+
+        let observer = Arc::new(CountingObserver::default());
+        let sr_settings = SrSettings::new_builder(server.url())
+            .set_observer(observer.clone())
+            .build()
+            .unwrap();
+        let decoder = AvroDecoder::new(sr_settings);
+        decoder.decode(Some(&[0, 0, 0, 0, 1, 6])).unwrap();
+        decoder.decode(Some(&[0, 0, 0, 0, 1, 6])).unwrap();
+
+        assert_eq!(observer.misses.load(Ordering::SeqCst), 1);
+        assert_eq!(observer.hits.load(Ordering::SeqCst), 1);
+        assert_eq!(*observer.fetches.lock().unwrap(), vec![true]);
+    }
+
+    #[derive(Debug)]
+    struct NotFoundIsRetryable;
+
+    impl ErrorClassifier for NotFoundIsRetryable {
+        fn is_retryable(&self, status: reqwest::StatusCode) -> bool {
+            status == reqwest::StatusCode::NOT_FOUND || status.is_server_error()
+        }
+    }
+
+    #[test]
+    fn test_decode_retry_with_error_classifier() {
+        let mut server = mockito::Server::new();
+        let not_found = server
+            .mock("GET", "/schemas/ids/1?deleted=true")
+            .with_status(404)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"error_code":40403,"message":"Schema not found"}"#)
+            .expect(1)
+            .create();
+
+        let sr_settings = SrSettings::new_builder(server.url())
+            .set_error_classifier(Arc::new(NotFoundIsRetryable))
+            .build()
+            .unwrap();
+        let decoder = AvroDecoder::new(sr_settings);
+        let error = decoder.decode(Some(&[0, 0, 0, 0, 1, 6])).unwrap_err();
+        assert!(error.is_retryable());
+        not_found.assert();
+        not_found.remove();
+
+        let found = server.mock("GET", "/schemas/ids/1?deleted=true")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"schema":"{\"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
+            .expect(1)
+            .create();
+        decoder.remove_errors_from_cache();
+        let heartbeat = decoder.decode(Some(&[0, 0, 0, 0, 1, 6])).unwrap().value;
+        assert_eq!(
+            heartbeat,
+            Value::Record(vec![("beat".to_string(), Value::Long(3))])
+        );
+        found.assert();
+    }
+
+    #[test]
+    fn test_decode_with_id() {
+        let mut server = mockito::Server::new();
+        let _m = server.mock("GET", "/schemas/ids/4?deleted=true")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"schema":"{\"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
+            .create();
+
+        let sr_settings = SrSettings::new(server.url());
+        let decoder = AvroDecoder::new(sr_settings);
+        let (id, result) = decoder
+            .decode_with_id(Some(&[0, 0, 0, 0, 4, 6]))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(id, 4);
+        assert_eq!(
+            result.value,
+            Value::Record(vec![("beat".to_string(), Value::Long(3))])
+        );
+        assert_eq!(decoder.decode_with_id(None), Ok(None));
+    }
+
     #[test]
     fn test_decoder_no_bytes() {
         let sr_settings = SrSettings::new(String::from("http://127.0.0.1:1234"));
@@ -922,7 +1560,13 @@ mod tests {
         let heartbeat = decoder.decode(Some(&bytes));
         assert_eq!(
             heartbeat,
-            Err(SRCError::new("Could not get raw schema from response", None, false).into_cache())
+            Err(SRCError::not_found(
+                "schema registry responded with status 404 Not Found",
+                Some(String::from(
+                    r#"{"error_code":40403,"message":"Schema not found"}"#
+                ))
+            )
+            .into_cache())
         );
         let _m = server.mock("GET", "/schemas/ids/2?deleted=true")
             .with_status(200)
@@ -933,7 +1577,13 @@ mod tests {
         let heartbeat = decoder.decode(Some(&bytes));
         assert_eq!(
             heartbeat,
-            Err(SRCError::new("Could not get raw schema from response", None, false).into_cache())
+            Err(SRCError::not_found(
+                "schema registry responded with status 404 Not Found",
+                Some(String::from(
+                    r#"{"error_code":40403,"message":"Schema not found"}"#
+                ))
+            )
+            .into_cache())
         );
 
         decoder.remove_errors_from_cache();
@@ -950,7 +1600,7 @@ mod tests {
         let sr_settings = SrSettings::new(String::from("http://127.0.0.1:1234"));
         let encoder = AvroEncoder::new(sr_settings);
         assert_eq!(
-            "AvroEncoder { sr_settings: SrSettings { urls: [\"http://127.0.0.1:1234\"], client: Client, authorization: None }, cache: {} }"
+            "AvroEncoder { sr_settings: SrSettings { urls: [\"http://127.0.0.1:1234\"], authorization: None }, cache: {}, id_encoding: ConfluentU32 }"
                 .to_owned(),
             format!("{:?}", encoder)
         )
@@ -1054,6 +1704,192 @@ mod tests {
         assert_eq!(bytes, Ok(vec![0, 0, 0, 0, 3, 6]))
     }
 
+    struct VersionedNamer;
+
+    impl SubjectNamer for VersionedNamer {
+        fn key_subject(&self, topic: &str, record: &str) -> String {
+            format!("{}.{}.key.v1", topic, record)
+        }
+
+        fn value_subject(&self, topic: &str, record: &str) -> String {
+            format!("{}.{}.v1", topic, record)
+        }
+    }
+
+    #[test]
+    fn test_encode_and_decode_with_apicurio_id() {
+        let mut server = mockito::Server::new();
+        let _m = server.mock("GET", "/subjects/heartbeat-value/versions/latest")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"subject":"heartbeat-value","version":1,"id":3,"schema":"{\"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
+            .create();
+        let _n = server.mock("GET", "/schemas/ids/3?deleted=true")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"schema":"{\"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
+            .create();
+
+        let mut encoder = AvroEncoder::new(SrSettings::new(server.url()));
+        encoder.set_id_encoding(IdEncoding::ApicurioU64);
+        let strategy = SubjectNameStrategy::TopicNameStrategy(String::from("heartbeat"), false);
+        let bytes = encoder
+            .encode_struct(Heartbeat { beat: 3 }, &strategy)
+            .unwrap();
+        assert_eq!(bytes, vec![0, 0, 0, 0, 0, 0, 0, 0, 3, 6]);
+
+        let mut decoder = AvroDecoder::new(SrSettings::new(server.url()));
+        decoder.set_id_encoding(IdEncoding::ApicurioU64);
+        let heartbeat = decoder.decode(Some(&bytes)).unwrap().value;
+        assert_eq!(
+            heartbeat,
+            Value::Record(vec![("beat".to_string(), Value::Long(3))])
+        );
+    }
+
+    #[derive(serde::Serialize)]
+    struct RenamedHeartbeat {
+        pulse: i64,
+    }
+
+    impl apache_avro::AvroSchema for RenamedHeartbeat {
+        fn get_schema() -> Schema {
+            Schema::parse_str(r#"{"type":"record","name":"Heartbeat","namespace":"nl.openweb.data","fields":[{"name":"pulse","type":"long"}]}"#).unwrap()
+        }
+    }
+
+    #[derive(serde::Serialize)]
+    struct VerifiedHeartbeat {
+        beat: i64,
+    }
+
+    impl apache_avro::AvroSchema for VerifiedHeartbeat {
+        fn get_schema() -> Schema {
+            Schema::parse_str(r#"{"type":"record","name":"Heartbeat","namespace":"nl.openweb.data","fields":[{"name":"beat","type":"long"}]}"#).unwrap()
+        }
+    }
+
+    #[test]
+    fn test_encode_struct_verified() {
+        let mut server = mockito::Server::new();
+        let _m = server.mock("GET", "/subjects/heartbeat-value/versions/latest")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"subject":"heartbeat-value","version":1,"id":3,"schema":"{\"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
+            .create();
+
+        let sr_settings = SrSettings::new(server.url());
+        let encoder = AvroEncoder::new(sr_settings);
+        let strategy = SubjectNameStrategy::TopicNameStrategy(String::from("heartbeat"), false);
+
+        let bytes = encoder
+            .encode_struct_verified(VerifiedHeartbeat { beat: 3 }, &strategy)
+            .unwrap();
+        assert_eq!(bytes, vec![0, 0, 0, 0, 3, 6]);
+
+        let error = encoder
+            .encode_struct_verified(RenamedHeartbeat { pulse: 3 }, &strategy)
+            .unwrap_err();
+        assert_eq!(
+            error.error,
+            "the schema of the item is not compatible with the schema with id 3"
+        );
+    }
+
+    #[test]
+    fn test_encode_struct_with_fixed_schema() {
+        let server = mockito::Server::new();
+        let sr_settings = SrSettings::new(server.url());
+        let encoder = AvroEncoder::new(sr_settings);
+        encoder
+            .add_fixed_schema(
+                "heartbeat-value",
+                3,
+                r#"{"type":"record","name":"Heartbeat","namespace":"nl.openweb.data","fields":[{"name":"beat","type":"long"}]}"#,
+            )
+            .unwrap();
+
+        let strategy = SubjectNameStrategy::TopicNameStrategy(String::from("heartbeat"), false);
+        let bytes = encoder
+            .encode_struct(Heartbeat { beat: 3 }, &strategy)
+            .unwrap();
+
+        assert_eq!(bytes, vec![0, 0, 0, 0, 3, 6]);
+    }
+
+    #[test]
+    fn test_encode_struct_with_fixed_schemas_from_dir() {
+        let server = mockito::Server::new();
+        let dir =
+            std::env::temp_dir().join(format!("blocking-avro-encoder-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("heartbeat-value.3.avsc"),
+            r#"{"type":"record","name":"Heartbeat","namespace":"nl.openweb.data","fields":[{"name":"beat","type":"long"}]}"#,
+        )
+        .unwrap();
+
+        let sr_settings = SrSettings::new(server.url());
+        let encoder = AvroEncoder::new(sr_settings);
+        let added = encoder.add_fixed_schemas_from_dir(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(added, Ok(1));
+
+        let strategy = SubjectNameStrategy::TopicNameStrategy(String::from("heartbeat"), false);
+        let bytes = encoder
+            .encode_struct(Heartbeat { beat: 3 }, &strategy)
+            .unwrap();
+
+        assert_eq!(bytes, vec![0, 0, 0, 0, 3, 6]);
+    }
+
+    #[test]
+    fn test_encode_struct_optional() {
+        let server = mockito::Server::new();
+        let sr_settings = SrSettings::new(server.url());
+        let encoder = AvroEncoder::new(sr_settings);
+        encoder
+            .add_fixed_schema(
+                "heartbeat-value",
+                3,
+                r#"{"type":"record","name":"Heartbeat","namespace":"nl.openweb.data","fields":[{"name":"beat","type":"long"}]}"#,
+            )
+            .unwrap();
+
+        let strategy = SubjectNameStrategy::TopicNameStrategy(String::from("heartbeat"), false);
+        let bytes = encoder
+            .encode_struct_optional(Some(Heartbeat { beat: 3 }), &strategy)
+            .unwrap();
+        assert_eq!(bytes, vec![0, 0, 0, 0, 3, 6]);
+
+        let bytes = encoder
+            .encode_struct_optional(None::<Heartbeat>, &strategy)
+            .unwrap();
+        assert!(bytes.is_empty());
+    }
+
+    #[test]
+    fn test_encode_struct_with_namer() {
+        let mut server = mockito::Server::new();
+        let _m = server.mock("GET", "/subjects/heartbeat.nl.openweb.data.Heartbeat.v1/versions/latest")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"subject":"heartbeat.nl.openweb.data.Heartbeat.v1","version":1,"id":3,"schema":"{\"type\":\"record\",\"name\":\"Heartbeat\",\"namespace\":\"nl.openweb.data\",\"fields\":[{\"name\":\"beat\",\"type\":\"long\"}]}"}"#)
+            .create();
+
+        let sr_settings = SrSettings::new(server.url());
+        let encoder = AvroEncoder::new(sr_settings);
+        let bytes = encoder.encode_struct_with_namer(
+            Heartbeat { beat: 3 },
+            &VersionedNamer,
+            "heartbeat",
+            "nl.openweb.data.Heartbeat",
+            false,
+        );
+
+        assert_eq!(bytes, Ok(vec![0, 0, 0, 0, 3, 6]));
+    }
+
     #[test]
     fn test_encoder_no_id_in_response() {
         let mut server = mockito::Server::new();
@@ -1152,7 +1988,13 @@ mod tests {
         let bytes = encoder.encode(vec![("beat", Value::Long(3))], &strategy);
         assert_eq!(
             bytes,
-            Err(SRCError::new("Could not get id from response", None, false).into_cache())
+            Err(SRCError::not_found(
+                "schema registry responded with status 404 Not Found",
+                Some(String::from(
+                    r#"{"error_code":40403,"message":"Schema not found"}"#
+                ))
+            )
+            .into_cache())
         );
 
         let _m = server.mock("GET", "/subjects/nl.openweb.data.Heartbeat/versions/latest")
@@ -1164,10 +2006,13 @@ mod tests {
         let bytes = encoder.encode(vec![("beat", Value::Long(3))], &strategy);
         assert_eq!(
             bytes,
-            Err(
-                SRCError::non_retryable_without_cause("Could not get id from response")
-                    .into_cache()
+            Err(SRCError::not_found(
+                "schema registry responded with status 404 Not Found",
+                Some(String::from(
+                    r#"{"error_code":40403,"message":"Schema not found"}"#
+                ))
             )
+            .into_cache())
         );
 
         encoder.remove_errors_from_cache();
@@ -1339,11 +2184,9 @@ mod tests {
         assert_eq!(
             error,
             Err(SRCError::new(
-                "could not parse to RawRegisteredSchema, schema might not exist on this schema registry, the http call failed, cause will give more information",
-                Some(String::from(
-                    "error decoding response body: EOF while parsing a value at line 1 column 0"
-                )),
-                false,
+                "schema registry responded with status 501 Not Implemented",
+                None,
+                true,
             )
             .into_cache())
         )
@@ -1477,4 +2320,109 @@ mod tests {
         };
         assert_eq!(&1i64, counter_value, "counter is 1");
     }
+
+    #[test]
+    fn transitive_references_resolved() {
+        let mut server = mockito::Server::new();
+        let sr_settings = SrSettings::new(server.url());
+        let decoder = AvroDecoder::new(sr_settings);
+        let _b = server.mock("GET", "/schemas/ids/9?deleted=true")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"schema":"{\"type\":\"record\",\"name\":\"Book\",\"namespace\":\"test\",\"fields\":[{\"name\":\"author\",\"type\":\"Author\"}]}","references":[{"name":"test.Author","subject":"author","version":1}]}"#)
+            .create();
+        let _a = server.mock("GET", "/subjects/author/versions/1")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"subject":"author","version":1,"id":10,"schema":"{\"type\":\"record\",\"name\":\"Author\",\"namespace\":\"test\",\"fields\":[{\"name\":\"address\",\"type\":\"Address\"}]}","references":[{"name":"test.Address","subject":"address","version":1}]}"#)
+            .create();
+        let _c = server.mock("GET", "/subjects/address/versions/1")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"subject":"address","version":1,"id":11,"schema":"{\"type\":\"record\",\"name\":\"Address\",\"namespace\":\"test\",\"fields\":[{\"name\":\"city\",\"type\":\"string\"}]}"}"#)
+            .create();
+
+        let result = decoder.decode(Some(&[0, 0, 0, 0, 9, 2, 97])).unwrap();
+        assert_eq!(
+            result.value,
+            Value::Record(vec![(
+                "author".to_string(),
+                Value::Record(vec![(
+                    "address".to_string(),
+                    Value::Record(vec![("city".to_string(), Value::String("a".to_string()))]),
+                )]),
+            )])
+        );
+    }
+
+    #[test]
+    fn reference_cycle_fails() {
+        let mut server = mockito::Server::new();
+        let sr_settings = SrSettings::new(server.url());
+        let decoder = AvroDecoder::new(sr_settings);
+        let _b = server.mock("GET", "/schemas/ids/9?deleted=true")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"schema":"{\"type\":\"record\",\"name\":\"Book\",\"namespace\":\"test\",\"fields\":[{\"name\":\"author\",\"type\":\"Author\"}]}","references":[{"name":"test.Author","subject":"author","version":1}]}"#)
+            .create();
+        let _a = server.mock("GET", "/subjects/author/versions/1")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"subject":"author","version":1,"id":10,"schema":"{\"type\":\"record\",\"name\":\"Author\",\"namespace\":\"test\",\"fields\":[{\"name\":\"address\",\"type\":\"Address\"}]}","references":[{"name":"test.Address","subject":"address","version":1}]}"#)
+            .create();
+        let _c = server.mock("GET", "/subjects/address/versions/1")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"subject":"address","version":1,"id":11,"schema":"{\"type\":\"record\",\"name\":\"Address\",\"namespace\":\"test\",\"fields\":[{\"name\":\"city\",\"type\":\"string\"}]}","references":[{"name":"test.Author","subject":"author","version":1}]}"#)
+            .create();
+
+        let error = decoder.decode(Some(&[0, 0, 0, 0, 9, 2, 97])).unwrap_err();
+        assert!(error.error.contains("refers back to itself"));
+    }
+
+    #[test]
+    fn shared_reference_defined_once() {
+        let mut server = mockito::Server::new();
+        let sr_settings = SrSettings::new(server.url());
+        let decoder = AvroDecoder::new(sr_settings);
+        let _o = server.mock("GET", "/schemas/ids/12?deleted=true")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"schema":"{\"type\":\"record\",\"name\":\"Order\",\"namespace\":\"test\",\"fields\":[{\"name\":\"b\",\"type\":\"Buyer\"},{\"name\":\"c\",\"type\":\"Seller\"}]}","references":[{"name":"test.Buyer","subject":"buyer","version":1},{"name":"test.Seller","subject":"seller","version":1}]}"#)
+            .create();
+        let _b = server.mock("GET", "/subjects/buyer/versions/1")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"subject":"buyer","version":1,"id":13,"schema":"{\"type\":\"record\",\"name\":\"Buyer\",\"namespace\":\"test\",\"fields\":[{\"name\":\"m\",\"type\":\"Money\"}]}","references":[{"name":"test.Money","subject":"money","version":1}]}"#)
+            .create();
+        let _s = server.mock("GET", "/subjects/seller/versions/1")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"subject":"seller","version":1,"id":14,"schema":"{\"type\":\"record\",\"name\":\"Seller\",\"namespace\":\"test\",\"fields\":[{\"name\":\"m\",\"type\":\"Money\"},{\"name\":\"n\",\"type\":\"Money\"}]}","references":[{"name":"test.Money","subject":"money","version":1}]}"#)
+            .create();
+        let _m = server.mock("GET", "/subjects/money/versions/1")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(r#"{"subject":"money","version":1,"id":15,"schema":"{\"type\":\"record\",\"name\":\"Money\",\"namespace\":\"test\",\"fields\":[{\"name\":\"amount\",\"type\":\"long\"}]}"}"#)
+            .create();
+
+        let result = decoder.decode(Some(&[0, 0, 0, 0, 12, 2, 4, 6])).unwrap();
+        let money = |amount| Value::Record(vec![("amount".to_string(), Value::Long(amount))]);
+        assert_eq!(
+            result.value,
+            Value::Record(vec![
+                (
+                    "b".to_string(),
+                    Value::Record(vec![("m".to_string(), money(1))])
+                ),
+                (
+                    "c".to_string(),
+                    Value::Record(vec![
+                        ("m".to_string(), money(2)),
+                        ("n".to_string(), money(3))
+                    ])
+                ),
+            ])
+        );
+    }
 }
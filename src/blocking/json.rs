@@ -1,7 +1,7 @@
 use std::collections::hash_map::{Entry, RandomState};
 use std::collections::HashMap;
-use std::str::FromStr;
 
+use log::warn;
 use serde_json::Value;
 use url::Url;
 use valico::json_schema::schema::ScopedSchema;
@@ -11,7 +11,10 @@ use crate::blocking::schema_registry::{
     get_referenced_schema, get_schema_by_id_and_type, get_schema_by_subject, SrSettings,
 };
 use crate::error::SRCError;
-use crate::json_common::{fetch_fallback, fetch_id, handle_validation, to_bytes, to_value};
+use crate::json_common::{
+    fetch_fallback, fetch_id, handle_validation, reference_url, scope_for_draft, to_bytes,
+    to_value, JsonSchemaDraft,
+};
 use crate::schema_registry_common::{
     get_bytes_result, BytesResult, RegisteredReference, RegisteredSchema, SchemaType,
     SubjectNameStrategy,
@@ -26,17 +29,29 @@ pub struct JsonEncoder {
     sr_settings: SrSettings,
     cache: HashMap<String, Result<EncodeContext, SRCError>, RandomState>,
     scope: Scope,
+    skip_validation_on_compile_error: bool,
 }
 
 impl JsonEncoder {
     /// Creates a new json encoder
     pub fn new(sr_settings: SrSettings) -> JsonEncoder {
+        JsonEncoder::new_with_draft(sr_settings, JsonSchemaDraft::default())
+    }
+    /// Creates a new json encoder, compiling the schemas using the supplied draft
+    pub fn new_with_draft(sr_settings: SrSettings, draft: JsonSchemaDraft) -> JsonEncoder {
         JsonEncoder {
             sr_settings,
             cache: HashMap::new(),
-            scope: Scope::new(),
+            scope: scope_for_draft(draft),
+            skip_validation_on_compile_error: false,
         }
     }
+    /// When set, values are encoded without validation for schemas that could not be compiled, for
+    /// example because they use a regex not supported by valico. A warning is logged once per
+    /// subject.
+    pub fn set_skip_validation_on_compile_error(&mut self, skip: bool) {
+        self.skip_validation_on_compile_error = skip;
+    }
     /// Removes errors from the cache, can be useful to retry failed encodings.
     pub fn remove_errors_from_cache(&mut self) {
         self.cache.retain(|_, v| v.is_ok());
@@ -62,18 +77,20 @@ impl JsonEncoder {
         value: &Value,
     ) -> Result<(ValidationState, u32), SRCError> {
         let cached_context = match self.cache.entry(key) {
-            Entry::Occupied(e) => e.into_mut().as_ref(),
+            Entry::Occupied(e) => {
+                self.sr_settings.observe_cache_hit();
+                e.into_mut().as_ref()
+            }
             Entry::Vacant(e) => {
+                self.sr_settings.observe_cache_miss();
                 let v = match get_schema_by_subject(&self.sr_settings, subject_name_strategy) {
-                    Ok(registered_schema) => match set_scoped_schema(
+                    Ok(registered_schema) => match encode_context(
                         &mut self.scope,
                         &self.sr_settings,
                         &registered_schema,
+                        self.skip_validation_on_compile_error,
                     ) {
-                        Ok(url) => Ok(EncodeContext {
-                            id: registered_schema.id,
-                            url,
-                        }),
+                        Ok(context) => Ok(context),
                         Err(e) => Err(e.into_cache()),
                     },
                     Err(e) => Err(e.into_cache()),
@@ -82,8 +99,9 @@ impl JsonEncoder {
             }
         };
         match cached_context {
-            Ok(context) => match self.scope.resolve(&context.url) {
-                Some(schema) => Ok((schema.validate(value), context.id)),
+            Ok(EncodeContext { id, url: None }) => Ok((ValidationState::new(), *id)),
+            Ok(EncodeContext { id, url: Some(url) }) => match self.scope.resolve(url) {
+                Some(schema) => Ok((schema.validate(value), *id)),
                 None => Err(SRCError::non_retryable_without_cause(
                     "could not get schema from scope",
                 )),
@@ -93,10 +111,36 @@ impl JsonEncoder {
     }
 }
 
+/// The id and the url of the schema in the scope, the url is none when the schema could not be
+/// compiled, and validation is skipped.
 #[derive(Debug)]
 struct EncodeContext {
     id: u32,
-    url: Url,
+    url: Option<Url>,
+}
+
+fn encode_context(
+    scope: &mut Scope,
+    sr_settings: &SrSettings,
+    registered_schema: &RegisteredSchema,
+    skip_validation_on_compile_error: bool,
+) -> Result<EncodeContext, SRCError> {
+    let (id, def) = prepare_scoped_schema(scope, sr_settings, registered_schema)?;
+    let url = match compile_scoped_schema(scope, id, def, registered_schema.id) {
+        Ok(url) => Some(url),
+        Err(e) if skip_validation_on_compile_error => {
+            warn!(
+                "could not compile json schema with id {}, values will not be validated: {}",
+                registered_schema.id, e
+            );
+            None
+        }
+        Err(e) => return Err(e),
+    };
+    Ok(EncodeContext {
+        id: registered_schema.id,
+        url,
+    })
 }
 
 #[derive(Debug)]
@@ -104,6 +148,7 @@ pub struct JsonDecoder {
     sr_settings: SrSettings,
     cache: HashMap<u32, Result<Url, SRCError>, RandomState>,
     scope: Scope,
+    subject_cache: HashMap<String, Url, RandomState>,
 }
 
 impl JsonDecoder {
@@ -113,10 +158,15 @@ impl JsonDecoder {
     /// comes back as an error you can use remove_errors_from_cache to clean the cache, keeping the
     /// correctly fetched schema's
     pub fn new(sr_settings: SrSettings) -> JsonDecoder {
+        JsonDecoder::new_with_draft(sr_settings, JsonSchemaDraft::default())
+    }
+    /// Creates a new json decoder, compiling the schemas using the supplied draft
+    pub fn new_with_draft(sr_settings: SrSettings, draft: JsonSchemaDraft) -> JsonDecoder {
         JsonDecoder {
             sr_settings,
             cache: HashMap::new(),
-            scope: Scope::new(),
+            scope: scope_for_draft(draft),
+            subject_cache: HashMap::new(),
         }
     }
     /// Remove al the errors from the cache, you might need to/want to run this when a recoverable
@@ -136,6 +186,82 @@ impl JsonDecoder {
             ))),
         }
     }
+    /// Decodes bytes without magic byte and id, using the schema with the given id. This is useful
+    /// when the id is known out of band, for example for data stored without the framing. Returns
+    /// none when the bytes are none.
+    pub fn decode_unframed(
+        &mut self,
+        id: u32,
+        bytes: Option<&[u8]>,
+    ) -> Result<Option<DecodeResult<'_>>, SRCError> {
+        match bytes {
+            None => Ok(None),
+            Some(bytes) => Ok(Some(self.deserialize(id, bytes)?)),
+        }
+    }
+    /// Like decode, but validating the value against the latest schema of the subject, ignoring the
+    /// id in the bytes. This way the consumer enforces the schema it expects. The schema for the
+    /// subject is only fetched and compiled once, and used until clear_subject_cache is called.
+    pub fn decode_validate_against_subject(
+        &mut self,
+        bytes: Option<&[u8]>,
+        subject_name_strategy: &SubjectNameStrategy,
+    ) -> Result<Option<DecodeResult<'_>>, SRCError> {
+        let bytes = match get_bytes_result(bytes) {
+            BytesResult::Null => return Ok(None),
+            BytesResult::Valid(_, bytes) => bytes,
+            BytesResult::Invalid(i) => {
+                return Err(SRCError::non_retryable_without_cause(&format!(
+                    "Invalid bytes: {:?}",
+                    i
+                )))
+            }
+        };
+        let url = self.subject_url(subject_name_strategy)?;
+        let value = match serde_json::from_slice(&bytes) {
+            Ok(v) => v,
+            Err(e) => {
+                return Err(SRCError::non_retryable_with_cause(
+                    e,
+                    "could not create value from bytes",
+                ))
+            }
+        };
+        match self.scope.resolve(&url) {
+            Some(schema) => {
+                handle_validation(schema.validate(&value), &value)?;
+                Ok(Some(DecodeResult { schema, value }))
+            }
+            None => Err(SRCError::non_retryable_without_cause(
+                "could not get schema from scope",
+            )),
+        }
+    }
+    /// Removes the schemas fetched for the subjects by decode_validate_against_subject, so the next
+    /// call fetches the latest schema again. A schema that was already compiled is reused, so a new
+    /// version with the same `$id` is not picked up.
+    pub fn clear_subject_cache(&mut self) {
+        self.subject_cache.clear();
+    }
+    /// Gets the url of the latest schema of the subject in the scope, fetching and compiling the
+    /// schema when it's not cached. Errors are not cached.
+    fn subject_url(
+        &mut self,
+        subject_name_strategy: &SubjectNameStrategy,
+    ) -> Result<Url, SRCError> {
+        let subject = subject_name_strategy.get_subject()?;
+        if let Some(url) = self.subject_cache.get(&subject) {
+            return Ok(url.clone());
+        }
+        let registered_schema = get_schema_by_subject(&self.sr_settings, subject_name_strategy)?;
+        let (url, def) =
+            prepare_scoped_schema(&mut self.scope, &self.sr_settings, &registered_schema)?;
+        if self.scope.resolve(&url).is_none() {
+            compile_scoped_schema(&mut self.scope, url.clone(), def, registered_schema.id)?;
+        }
+        self.subject_cache.insert(subject, url.clone());
+        Ok(url)
+    }
     /// The actual deserialization trying to get the id from the bytes to retrieve the schema, and
     /// using a reader transforms the bytes to a value.
     fn deserialize(&mut self, id: u32, bytes: &[u8]) -> Result<DecodeResult, SRCError> {
@@ -152,8 +278,12 @@ impl JsonDecoder {
     /// it into the cache.
     fn schema(&mut self, id: u32) -> Result<ScopedSchema, SRCError> {
         let url = match self.cache.entry(id) {
-            Entry::Occupied(e) => &*e.into_mut(),
+            Entry::Occupied(e) => {
+                self.sr_settings.observe_cache_hit();
+                &*e.into_mut()
+            }
             Entry::Vacant(e) => {
+                self.sr_settings.observe_cache_miss();
                 let v = match get_schema_by_id_and_type(id, &self.sr_settings, SchemaType::Json) {
                     Ok(r) => match set_scoped_schema(&mut self.scope, &self.sr_settings, &r) {
                         Ok(schema) => Ok(schema),
@@ -179,21 +309,27 @@ impl JsonDecoder {
 fn add_refs_to_scope(
     scope: &mut Scope,
     sr_settings: &SrSettings,
+    base: &Url,
     refs: &[RegisteredReference],
 ) -> Result<(), SRCError> {
     for rr in refs.iter() {
-        let rs = get_referenced_schema(sr_settings, rr)?;
-        let id = match Url::from_str(&rr.name) {
-            Ok(v) => v,
-            Err(e) => return Err(SRCError::non_retryable_with_cause(e, &format!("reference schema with subject {} and version {} has invalid id {}, it has to be a fully qualified url", rr.subject, rr.version, rr.name)))
-        };
+        let id = reference_url(base, rr)?;
         // if it's already part of the scope, it's assumed any references are also already part of the scope.
         if scope.resolve(&id).is_some() {
-            return Ok(());
+            continue;
         }
-        add_refs_to_scope(scope, sr_settings, &rs.references)?;
+        let rs = get_referenced_schema(sr_settings, rr)?;
+        add_refs_to_scope(scope, sr_settings, &id, &rs.references)?;
         let def: Value = to_value(&rs.schema)?;
-        scope.compile_with_id(&id, def, false).unwrap();
+        if let Err(e) = scope.compile_with_id(&id, def, false) {
+            return Err(SRCError::non_retryable_with_cause(
+                e,
+                &format!(
+                    "could not compile reference schema with subject {} and version {}",
+                    rr.subject, rr.version
+                ),
+            ));
+        }
     }
     Ok(())
 }
@@ -203,7 +339,17 @@ fn set_scoped_schema(
     sr_settings: &SrSettings,
     registered_schema: &RegisteredSchema,
 ) -> Result<Url, SRCError> {
-    add_refs_to_scope(scope, sr_settings, &registered_schema.references)?;
+    let (id, def) = prepare_scoped_schema(scope, sr_settings, registered_schema)?;
+    compile_scoped_schema(scope, id, def, registered_schema.id)
+}
+
+/// Parses the schema and adds the references to the scope, returning the url and the value of the
+/// schema, so it can be compiled.
+fn prepare_scoped_schema(
+    scope: &mut Scope,
+    sr_settings: &SrSettings,
+    registered_schema: &RegisteredSchema,
+) -> Result<(Url, Value), SRCError> {
     let def: Value = match serde_json::from_str(&registered_schema.schema) {
         Ok(v) => v,
         Err(e) => {
@@ -218,14 +364,24 @@ fn set_scoped_schema(
     };
     let id = match fetch_id(&def) {
         Some(url) => url,
-        None => fetch_fallback(sr_settings.url(), registered_schema.id),
+        None => fetch_fallback(sr_settings.url(), registered_schema.id)?,
     };
+    add_refs_to_scope(scope, sr_settings, &id, &registered_schema.references)?;
+    Ok((id, def))
+}
+
+fn compile_scoped_schema(
+    scope: &mut Scope,
+    id: Url,
+    def: Value,
+    schema_id: u32,
+) -> Result<Url, SRCError> {
     match scope.compile_with_id(&id, def, false) {
         Ok(_) => (),
         Err(e) => {
             return Err(SRCError::non_retryable_with_cause(
                 e,
-                &format!("could not compile schema with id {}", registered_schema.id),
+                &format!("could not compile schema with id {}", schema_id),
             ))
         }
     };
@@ -247,6 +403,7 @@ mod tests {
 
     use crate::blocking::json::{JsonDecoder, JsonEncoder};
     use crate::blocking::schema_registry::SrSettings;
+    use crate::json_common::JsonSchemaDraft;
     use crate::schema_registry_common::{get_payload, SubjectNameStrategy};
     use test_utils::{
         get_json_body, get_json_body_with_reference, json_get_result_references,
@@ -317,6 +474,32 @@ mod tests {
         assert_eq!(encoded_data, json_result_java_bytes())
     }
 
+    #[test]
+    fn test_encode_skip_validation_on_compile_error() {
+        let mut server = mockito::Server::new();
+        let _m = server
+            .mock("GET", "/subjects/testresult-value/versions/latest")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(get_json_body(
+                r#"{\"type\":\"object\",\"properties\":{\"up\":{\"type\":\"string\",\"pattern\":\"(?<=a+)b\"}}}"#,
+                10,
+            ))
+            .create();
+
+        let mut strict = JsonEncoder::new(SrSettings::new(server.url()));
+        let mut lenient = JsonEncoder::new(SrSettings::new(server.url()));
+        lenient.set_skip_validation_on_compile_error(true);
+        let strategy = SubjectNameStrategy::TopicNameStrategy(String::from("testresult"), false);
+        let value: Value = from_str(r#"{"up":"aab"}"#).unwrap();
+
+        let error = strict.encode(&value, &strategy).unwrap_err();
+        assert_eq!(error.error, "could not compile schema with id 10");
+        let bytes = lenient.encode(&value, &strategy).unwrap();
+        assert_eq!(bytes[4], 10);
+        assert!(lenient.encode(&value, &strategy).is_ok());
+    }
+
     #[test]
     fn test_encode_clean_cache() {
         let mut server = mockito::Server::new();
@@ -670,7 +853,7 @@ mod tests {
         let sr_settings = SrSettings::new(String::from("http://127.0.0.1:1234"));
         let encoder = JsonEncoder::new(sr_settings);
         assert!(
-            format!("{:?}", encoder).starts_with("JsonEncoder { sr_settings: SrSettings { urls: [\"http://127.0.0.1:1234\"], client: Client, authorization: None }, cache: {}, scope: Scope {")
+            format!("{:?}", encoder).starts_with("JsonEncoder { sr_settings: SrSettings { urls: [\"http://127.0.0.1:1234\"], authorization: None }, cache: {}, scope: Scope {")
         )
     }
 
@@ -679,7 +862,7 @@ mod tests {
         let sr_settings = SrSettings::new(String::from("http://127.0.0.1:1234"));
         let decoder = JsonDecoder::new(sr_settings);
         assert!(
-                   format!("{:?}", decoder).starts_with("JsonDecoder { sr_settings: SrSettings { urls: [\"http://127.0.0.1:1234\"], client: Client, authorization: None }, cache: {}, scope: Scope {")
+                   format!("{:?}", decoder).starts_with("JsonDecoder { sr_settings: SrSettings { urls: [\"http://127.0.0.1:1234\"], authorization: None }, cache: {}, scope: Scope {")
         )
     }
 
@@ -732,6 +915,101 @@ mod tests {
             .ends_with("was not valid because of missing references"))
     }
 
+    #[test]
+    fn test_encode_reference_not_compiling() {
+        let mut server = mockito::Server::new();
+        let _m = server
+            .mock("GET", "/subjects/testresult-value/versions/latest")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(get_json_body_with_reference(
+                json_test_ref_schema(),
+                10,
+                json_get_result_references(),
+            ))
+            .create();
+        let _m = server
+            .mock("GET", "/subjects/result.json/versions/1")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(get_json_body(
+                r#"{\"type\":\"object\",\"properties\":5}"#,
+                4,
+            ))
+            .create();
+
+        let sr_settings = SrSettings::new(server.url());
+        let mut encoder = JsonEncoder::new(sr_settings);
+        let strategy = SubjectNameStrategy::TopicNameStrategy(String::from("testresult"), false);
+        let result_example: Value =
+            serde_json::from_reader(File::open("tests/schema/jsontest-example.json").unwrap())
+                .unwrap();
+
+        let error = encoder.encode(&result_example, &strategy).unwrap_err();
+
+        assert_eq!(
+            error.error,
+            "could not compile reference schema with subject result.json and version 1"
+        );
+        assert!(error.cached)
+    }
+
+    #[test]
+    fn test_encode_relative_reference() {
+        let mut server = mockito::Server::new();
+        let _m = server
+            .mock("GET", "/subjects/testresult-value/versions/latest")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(get_json_body_with_reference(
+                r#"{\"type\":\"object\",\"properties\":{\"result\":{\"$ref\":\"result.json\"}},\"required\":[\"result\"]}"#,
+                10,
+                r#"{"name": "result.json", "subject": "result.json", "version": 1}"#,
+            ))
+            .create();
+        let _m = server
+            .mock("GET", "/subjects/result.json/versions/1")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(get_json_body(json_result_schema(), 4))
+            .create();
+
+        let sr_settings = SrSettings::new(server.url());
+        let mut encoder = JsonEncoder::new(sr_settings);
+        let strategy = SubjectNameStrategy::TopicNameStrategy(String::from("testresult"), false);
+        let value: Value =
+            serde_json::from_str(r#"{"result":{"up":"STRING","down":"string"}}"#).unwrap();
+
+        let result = encoder.encode(&value, &strategy).unwrap();
+
+        assert_eq!(result[4], 10)
+    }
+
+    #[test]
+    fn test_encode_with_draft() {
+        let mut server = mockito::Server::new();
+        let _m = server
+            .mock("GET", "/subjects/testresult-value/versions/latest")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(get_json_body(
+                r#"{\"type\":\"object\",\"properties\":{\"up\":{\"type\":\"string\"}},\"unevaluatedProperties\":false}"#,
+                10,
+            ))
+            .create();
+
+        let mut draft_7 = JsonEncoder::new(SrSettings::new(server.url()));
+        let mut draft_2019_09 = JsonEncoder::new_with_draft(
+            SrSettings::new(server.url()),
+            JsonSchemaDraft::Draft2019_09,
+        );
+        let strategy = SubjectNameStrategy::TopicNameStrategy(String::from("testresult"), false);
+        let value: Value = serde_json::from_str(r#"{"up":"STRING","down":"string"}"#).unwrap();
+
+        assert!(draft_7.encode(&value, &strategy).is_ok());
+        assert!(draft_2019_09.encode(&value, &strategy).is_err());
+    }
+
     #[test]
     fn decode_invalid_bytes() {
         let sr_settings = SrSettings::new(String::from("http://127.0.0.1:1234"));
@@ -739,4 +1017,75 @@ mod tests {
         let result = decoder.decode(Some(&[1, 0])).unwrap_err();
         assert_eq!(String::from("Invalid bytes: [1, 0]"), result.error)
     }
+
+    #[test]
+    fn decode_validate_against_subject_ignores_id() {
+        let mut server = mockito::Server::new();
+        let _i = server
+            .mock("GET", "/schemas/ids/10?deleted=true")
+            .expect(0)
+            .create();
+        let _m = server
+            .mock("GET", "/subjects/testresult-value/versions/latest")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(get_json_body(json_result_schema(), 12))
+            .expect(1)
+            .create();
+        let _n = server
+            .mock("GET", "/subjects/other-value/versions/latest")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(get_json_body(
+                r#"{\"type\":\"object\",\"properties\":{\"up\":{\"type\":\"integer\"}}}"#,
+                13,
+            ))
+            .create();
+
+        let sr_settings = SrSettings::new(server.url());
+        let mut decoder = JsonDecoder::new(sr_settings);
+        let strategy = SubjectNameStrategy::TopicNameStrategy(String::from("testresult"), false);
+        let other = SubjectNameStrategy::TopicNameStrategy(String::from("other"), false);
+
+        for _ in 0..2 {
+            let message = decoder
+                .decode_validate_against_subject(Some(json_result_java_bytes()), &strategy)
+                .unwrap()
+                .unwrap();
+            assert_eq!("STRING", message.value["up"]);
+        }
+        let error = decoder
+            .decode_validate_against_subject(Some(json_result_java_bytes()), &other)
+            .unwrap_err();
+        assert!(error
+            .error
+            .contains("was not valid according to the schema"));
+        _i.assert();
+        _m.assert();
+    }
+
+    #[test]
+    fn decode_validate_against_subject_after_clear_subject_cache() {
+        let mut server = mockito::Server::new();
+        let _m = server
+            .mock("GET", "/subjects/testresult-value/versions/latest")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(get_json_body(json_result_schema(), 12))
+            .expect(2)
+            .create();
+
+        let sr_settings = SrSettings::new(server.url());
+        let mut decoder = JsonDecoder::new(sr_settings);
+        let strategy = SubjectNameStrategy::TopicNameStrategy(String::from("testresult"), false);
+
+        assert!(decoder
+            .decode_validate_against_subject(Some(json_result_java_bytes()), &strategy)
+            .is_ok());
+        decoder.clear_subject_cache();
+        assert!(decoder
+            .decode_validate_against_subject(Some(json_result_java_bytes()), &strategy)
+            .is_ok());
+        _m.assert();
+    }
 }
@@ -8,7 +8,10 @@ use crate::blocking::schema_registry::{
 };
 use crate::error::SRCError;
 use crate::proto_common_types::add_common_files;
-use crate::proto_resolver::{resolve_name, to_index_and_data, MessageResolver};
+use crate::proto_decoder_common::{
+    add_default_values, decode_message, get_message, map_well_known_types,
+};
+use crate::proto_resolver::{imports_of_all, resolve_name, to_index_and_data, MessageResolver};
 use crate::schema_registry_common::{get_bytes_result, BytesResult, RegisteredSchema, SchemaType};
 use protofish::context::Context;
 use protofish::decode::{MessageValue, Value};
@@ -51,6 +54,24 @@ impl ProtoDecoder {
             BytesResult::Invalid(i) => Ok(Value::Bytes(Bytes::from(i))),
         }
     }
+    /// Decodes bytes without magic byte and id, but still starting with the message index, using the
+    /// schema with the given id. This is useful when the id is known out of band, for example for
+    /// data stored without the framing. Returns none when the bytes are none.
+    pub fn decode_unframed(
+        &self,
+        id: u32,
+        bytes: Option<&[u8]>,
+    ) -> Result<Option<Value>, SRCError> {
+        match bytes {
+            None => Ok(None),
+            Some([]) => Err(SRCError::non_retryable_without_cause(
+                "Bytes are empty, the message index is missing",
+            )),
+            Some(bytes) => Ok(Some(Value::Message(Box::from(
+                self.deserialize(id, bytes)?,
+            )))),
+        }
+    }
     /// The actual deserialization trying to get the id from the bytes to retrieve the schema, and
     /// using a reader transforms the bytes to a value.
     fn deserialize(&self, id: u32, bytes: &[u8]) -> Result<MessageValue, SRCError> {
@@ -59,11 +80,66 @@ impl ProtoDecoder {
                 let (index, data) = to_index_and_data(bytes);
                 let full_name = resolve_name(&s.resolver, &index)?;
                 let message_info = s.context.get_message(&full_name).unwrap();
-                Ok(message_info.decode(&data, &s.context))
+                Ok(decode_message(message_info, &data, &s.context))
             }
             Err(e) => Err(e),
         }
     }
+    /// Decodes bytes into a value, like decode, but as the message with the full name, like
+    /// `nl.openweb.data.Heartbeat`, instead of the message selected by the index in the bytes. This
+    /// can be used when a producer writes the wrong index. Fails when the schema doesn't contain
+    /// the message.
+    pub fn decode_as(
+        &self,
+        bytes: Option<&[u8]>,
+        message_full_name: &str,
+    ) -> Result<Value, SRCError> {
+        match get_bytes_result(bytes) {
+            BytesResult::Null => Ok(Value::Bytes(Bytes::new())),
+            BytesResult::Valid(id, bytes) => {
+                let context = self.context(id)?;
+                let (_, data) = to_index_and_data(&bytes);
+                let message_info = get_message(&context.context, message_full_name)?;
+                Ok(Value::Message(Box::from(decode_message(
+                    message_info,
+                    &data,
+                    &context.context,
+                ))))
+            }
+            BytesResult::Invalid(i) => Ok(Value::Bytes(Bytes::from(i))),
+        }
+    }
+    /// Decodes bytes into a value, like decode, but with the default value added for proto3 scalar
+    /// and enum fields that are not in the bytes, see
+    /// [add_default_values](crate::proto_decoder_common::add_default_values).
+    pub fn decode_with_default_values(&self, bytes: Option<&[u8]>) -> Result<Value, SRCError> {
+        match get_bytes_result(bytes) {
+            BytesResult::Null => Ok(Value::Bytes(Bytes::new())),
+            BytesResult::Valid(id, bytes) => {
+                let result = self.deserialize_with_context(id, &bytes)?;
+                Ok(Value::Message(Box::from(add_default_values(
+                    result.value,
+                    &result.context.context,
+                ))))
+            }
+            BytesResult::Invalid(i) => Ok(Value::Bytes(Bytes::from(i))),
+        }
+    }
+    /// Decodes bytes into a value, like decode, but with the google.protobuf.Timestamp and
+    /// google.protobuf.Duration values replaced by strings, as they would be mapped to json.
+    pub fn decode_with_well_known_types(&self, bytes: Option<&[u8]>) -> Result<Value, SRCError> {
+        match get_bytes_result(bytes) {
+            BytesResult::Null => Ok(Value::Bytes(Bytes::new())),
+            BytesResult::Valid(id, bytes) => {
+                let result = self.deserialize_with_context(id, &bytes)?;
+                Ok(Value::Message(Box::from(map_well_known_types(
+                    result.value,
+                    &result.context.context,
+                ))))
+            }
+            BytesResult::Invalid(i) => Ok(Value::Bytes(Bytes::from(i))),
+        }
+    }
     /// Decodes bytes into a decode result.
     /// The choice to use Option<&[u8]> as type us made so it plays nice with the BorrowedMessage
     /// struct from rdkafka, for example if we have m: &'a BorrowedMessage and decoder: &'a
@@ -96,7 +172,7 @@ impl ProtoDecoder {
                 let (index, data_bytes) = to_index_and_data(bytes);
                 let full_name = resolve_name(&s.resolver, &index)?;
                 let message_info = s.context.get_message(&full_name).unwrap();
-                let value = message_info.decode(&data_bytes, &s.context);
+                let value = decode_message(message_info, &data_bytes, &s.context);
                 Ok(DecodeResultWithContext {
                     value,
                     context: s.clone(),
@@ -107,12 +183,22 @@ impl ProtoDecoder {
             Err(e) => Err(e),
         }
     }
+    /// Gets the context of the schema with the id, which contains the parsed messages and enums,
+    /// including those of the references. This can be used to inspect the schema, for example
+    /// to list the fields of a message.
+    pub fn get_decode_context(&self, id: u32) -> Result<Arc<DecodeContext>, SRCError> {
+        self.context(id)
+    }
     /// Gets the Context object, either from the cache, or from the schema registry and then putting
     /// it into the cache.
     fn context(&self, id: u32) -> Result<Arc<DecodeContext>, SRCError> {
         match self.cache.entry(id) {
-            Entry::Occupied(e) => e.get().clone(),
+            Entry::Occupied(e) => {
+                self.sr_settings.observe_cache_hit();
+                e.get().clone()
+            }
             Entry::Vacant(e) => {
+                self.sr_settings.observe_cache_miss();
                 let v = match get_schema_by_id_and_type(id, &self.sr_settings, SchemaType::Protobuf)
                 {
                     Ok(v) => to_resolve_context(&self.sr_settings, v),
@@ -141,7 +227,9 @@ fn add_files(
         let child_schema = get_referenced_schema(sr_settings, &r)?;
         add_files(sr_settings, child_schema, files)?;
     }
-    files.push(registered_schema.schema);
+    if !files.contains(&registered_schema.schema) {
+        files.push(registered_schema.schema);
+    }
     Ok(())
 }
 
@@ -157,9 +245,11 @@ fn to_resolve_context(
     registered_schema: RegisteredSchema,
 ) -> Result<Arc<DecodeContext>, SRCError> {
     let resolver = MessageResolver::new(&registered_schema.schema);
+    let mut schemas = Vec::new();
+    add_files(sr_settings, registered_schema.clone(), &mut schemas)?;
     let mut files = Vec::new();
-    add_common_files(resolver.imports(), &mut files);
-    add_files(sr_settings, registered_schema.clone(), &mut files)?;
+    add_common_files(&imports_of_all(&schemas), &mut files);
+    files.append(&mut schemas);
     match Context::parse(&files) {
         Ok(context) => Ok(Arc::new(DecodeContext {
             resolver,
@@ -231,6 +321,134 @@ mod tests {
         assert_eq!(Value::UInt64(101u64), message.fields[0].value)
     }
 
+    #[test]
+    fn test_decoder_default_values() {
+        let mut server = mockito::Server::new();
+        let _m = server
+            .mock("GET", "/schemas/ids/4?deleted=true")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(get_proto_body(
+                r#"syntax = \"proto3\"; package nl.openweb.data; message Heartbeat {uint64 beat = 1; optional string source = 2;}"#,
+                4,
+            ))
+            .create();
+
+        let sr_settings = SrSettings::new(server.url());
+        let decoder = ProtoDecoder::new(sr_settings);
+        let heartbeat = decoder
+            .decode_with_default_values(Some(&[0, 0, 0, 0, 4, 0]))
+            .unwrap();
+
+        let message = match heartbeat {
+            Value::Message(x) => *x,
+            v => panic!("Other value: {:?} than expected Message", v),
+        };
+        assert_eq!(1, message.fields.len());
+        assert_eq!(Value::UInt64(0), message.fields[0].value)
+    }
+
+    #[test]
+    fn test_decode_as() {
+        let mut server = mockito::Server::new();
+        let _m = server
+            .mock("GET", "/schemas/ids/5?deleted=true")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(get_proto_body(
+                r#"syntax = \"proto3\"; package nl.openweb.data; message Other {string name = 1;} message Heartbeat {uint64 beat = 1;}"#,
+                5,
+            ))
+            .create();
+
+        let sr_settings = SrSettings::new(server.url());
+        let decoder = ProtoDecoder::new(sr_settings);
+        let heartbeat = decoder
+            .decode_as(
+                Some(&[0, 0, 0, 0, 5, 0, 8, 101]),
+                "nl.openweb.data.Heartbeat",
+            )
+            .unwrap();
+
+        let message = match heartbeat {
+            Value::Message(x) => *x,
+            v => panic!("Other value: {:?} than expected Message", v),
+        };
+        assert_eq!(Value::UInt64(101u64), message.fields[0].value);
+
+        let error = decoder
+            .decode_as(Some(&[0, 0, 0, 0, 5, 0, 8, 101]), "nl.openweb.data.Missing")
+            .unwrap_err();
+        assert_eq!(
+            error.error,
+            "Could not find message nl.openweb.data.Missing in the schema"
+        )
+    }
+
+    #[test]
+    fn test_decode_unframed() {
+        let mut server = mockito::Server::new();
+        let _m = server
+            .mock("GET", "/schemas/ids/7?deleted=true")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(get_proto_body(get_proto_hb_schema(), 1))
+            .create();
+
+        let sr_settings = SrSettings::new(server.url());
+        let decoder = ProtoDecoder::new(sr_settings);
+        let heartbeat = decoder
+            .decode_unframed(7, Some(&get_proto_hb_101()[5..]))
+            .unwrap();
+
+        let message = match heartbeat {
+            Some(Value::Message(x)) => *x,
+            v => panic!("Other value: {:?} than expected Message", v),
+        };
+        assert_eq!(Value::UInt64(101u64), message.fields[0].value);
+        assert_eq!(None, decoder.decode_unframed(7, None).unwrap());
+    }
+
+    #[test]
+    fn test_decode_unframed_empty() {
+        let sr_settings = SrSettings::new(String::from("http://127.0.0.1:1234"));
+        let decoder = ProtoDecoder::new(sr_settings);
+        let error = decoder.decode_unframed(7, Some(&[])).unwrap_err();
+        assert_eq!(error.error, "Bytes are empty, the message index is missing");
+        assert!(!error.retriable);
+    }
+
+    #[test]
+    fn test_decoder_well_known_types() {
+        let mut server = mockito::Server::new();
+        let _m = server
+            .mock("GET", "/schemas/ids/3?deleted=true")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(get_proto_body(
+                r#"syntax = \"proto3\"; package nl.openweb.data; import \"google/protobuf/timestamp.proto\"; message Event {google.protobuf.Timestamp at = 1;}"#,
+                3,
+            ))
+            .create();
+
+        let sr_settings = SrSettings::new(server.url());
+        let decoder = ProtoDecoder::new(sr_settings);
+        let event = decoder
+            .decode_with_well_known_types(Some(&[
+                0, 0, 0, 0, 3, 0, 10, 12, 8, 128, 204, 185, 255, 5, 16, 128, 202, 181, 238, 1,
+            ]))
+            .unwrap();
+
+        let message = match event {
+            Value::Message(x) => *x,
+            v => panic!("Other value: {:?} than expected Message", v),
+        };
+        assert_eq!(
+            Value::String(String::from("2021-01-01T00:00:00.500Z")),
+            message.fields[0].value
+        )
+    }
+
     #[test]
     fn test_decoder_cache() {
         let mut server = mockito::Server::new();
@@ -262,6 +480,59 @@ mod tests {
         assert_eq!(Value::UInt64(101u64), message.fields[0].value);
     }
 
+    #[test]
+    fn test_decoder_shared_reference_with_well_known_types() {
+        let mut server = mockito::Server::new();
+        let _m = server
+            .mock("GET", "/schemas/ids/9?deleted=true")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(get_proto_body_with_reference(
+                r#"syntax = \"proto3\"; package nl.openweb.data; import \"a.proto\"; import \"event.proto\"; message Holder {A a = 1; Event event = 2;}"#,
+                9,
+                r#"{"name": "a.proto", "subject": "a", "version": 1}, {"name": "event.proto", "subject": "event", "version": 1}"#,
+            ))
+            .create();
+        let _m = server
+            .mock("GET", "/subjects/a/versions/1")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(get_proto_body_with_reference(
+                r#"syntax = \"proto3\"; package nl.openweb.data; import \"event.proto\"; message A {Event event = 1;}"#,
+                10,
+                r#"{"name": "event.proto", "subject": "event", "version": 1}"#,
+            ))
+            .create();
+        let _m = server
+            .mock("GET", "/subjects/event/versions/1")
+            .with_status(200)
+            .with_header("content-type", "application/vnd.schemaregistry.v1+json")
+            .with_body(get_proto_body(
+                r#"syntax = \"proto3\"; package nl.openweb.data; import \"google/protobuf/timestamp.proto\"; message Event {google.protobuf.Timestamp at = 1;}"#,
+                11,
+            ))
+            .create();
+
+        let sr_settings = SrSettings::new(server.url());
+        let decoder = ProtoDecoder::new(sr_settings);
+        let holder = decoder
+            .decode_with_well_known_types(Some(&[0, 0, 0, 0, 9, 0, 18, 4, 10, 2, 8, 5]))
+            .unwrap();
+
+        let message = match holder {
+            Value::Message(x) => *x,
+            v => panic!("Other value: {:?} than expected Message", v),
+        };
+        let event = match &message.fields[0].value {
+            Value::Message(x) => x,
+            v => panic!("Other value: {:?} than expected Message", v),
+        };
+        assert_eq!(
+            Value::String(String::from("1970-01-01T00:00:05Z")),
+            event.fields[0].value
+        )
+    }
+
     #[test]
     fn test_decoder_complex() {
         let mut server = mockito::Server::new();
@@ -301,7 +572,7 @@ mod tests {
         let sr_settings = SrSettings::new("http://127.0.0.1:1234".to_string());
         let decoder = ProtoDecoder::new(sr_settings);
         assert_eq!(
-            "ProtoDecoder { sr_settings: SrSettings { urls: [\"http://127.0.0.1:1234\"], client: Client, authorization: None }, cache: {} }"
+            "ProtoDecoder { sr_settings: SrSettings { urls: [\"http://127.0.0.1:1234\"], authorization: None }, cache: {} }"
                 .to_owned(),
             format!("{:?}", decoder)
         )
@@ -69,8 +69,12 @@ impl ProtoRawEncoder {
         subject_name_strategy: &SubjectNameStrategy,
     ) -> Result<Arc<EncodeContext>, SRCError> {
         match self.cache.entry(key) {
-            Entry::Occupied(e) => e.get().clone(),
+            Entry::Occupied(e) => {
+                self.sr_settings.observe_cache_hit();
+                e.get().clone()
+            }
             Entry::Vacant(e) => {
+                self.sr_settings.observe_cache_miss();
                 let v = match get_schema_by_subject(&self.sr_settings, subject_name_strategy) {
                     Ok(registered_schema) => Ok(Arc::new(EncodeContext {
                         id: registered_schema.id,
@@ -140,8 +144,12 @@ impl ProtoRawDecoder {
     /// it into the cache.
     fn context(&self, id: u32) -> Result<Arc<DecodeContext>, SRCError> {
         match self.cache.entry(id) {
-            Entry::Occupied(e) => e.get().clone(),
+            Entry::Occupied(e) => {
+                self.sr_settings.observe_cache_hit();
+                e.get().clone()
+            }
             Entry::Vacant(e) => {
+                self.sr_settings.observe_cache_miss();
                 let v = match get_schema_by_id_and_type(id, &self.sr_settings, SchemaType::Protobuf)
                 {
                     Ok(r) => Ok(Arc::new(to_decode_context(r))),
@@ -344,7 +352,7 @@ mod tests {
         let sr_settings = SrSettings::new(String::from("http://127.0.0.1:1234"));
         let decoder = ProtoRawEncoder::new(sr_settings);
         assert_eq!(
-            "ProtoRawEncoder { sr_settings: SrSettings { urls: [\"http://127.0.0.1:1234\"], client: Client, authorization: None }, cache: {} }"
+            "ProtoRawEncoder { sr_settings: SrSettings { urls: [\"http://127.0.0.1:1234\"], authorization: None }, cache: {} }"
                 .to_owned(),
             format!("{:?}", decoder)
         )
@@ -440,7 +448,7 @@ mod tests {
         let sr_settings = SrSettings::new(String::from("http://127.0.0.1:1234"));
         let decoder = ProtoRawDecoder::new(sr_settings);
         assert_eq!(
-            "ProtoRawDecoder { sr_settings: SrSettings { urls: [\"http://127.0.0.1:1234\"], client: Client, authorization: None }, cache: {} }"
+            "ProtoRawDecoder { sr_settings: SrSettings { urls: [\"http://127.0.0.1:1234\"], authorization: None }, cache: {} }"
                 .to_owned(),
             format!("{:?}", decoder)
         )
@@ -2,6 +2,8 @@ use std::error::Error;
 use std::fmt;
 use std::fmt::Display;
 
+use serde::Deserialize;
+
 /// Error struct which makes it easy to know if the resulting error is also preserved in the cache
 /// or not. And whether trying it again might not cause an error.
 #[derive(Debug, PartialEq)]
@@ -10,6 +12,16 @@ pub struct SRCError {
     pub cause: Option<String>,
     pub retriable: bool,
     pub cached: bool,
+    pub(crate) not_found: bool,
+}
+
+/// The error the schema registry responds with, like `{"error_code":40401,"message":"Subject not
+/// found"}`. Some implementations use description instead of message.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct RegistryError {
+    pub error_code: i32,
+    #[serde(alias = "description")]
+    pub message: String,
 }
 
 /// Implements standard error so error handling can be simplified
@@ -23,6 +35,7 @@ impl Clone for SRCError {
             cause: self.cause.as_ref().cloned(),
             retriable: self.retriable,
             cached: self.cached,
+            not_found: self.not_found,
         }
     }
 }
@@ -54,6 +67,7 @@ impl SRCError {
             cause,
             retriable,
             cached: false,
+            not_found: false,
         }
     }
     pub fn retryable_with_cause<T: Display>(cause: T, error: &str) -> SRCError {
@@ -65,6 +79,32 @@ impl SRCError {
     pub fn non_retryable_without_cause(error: &str) -> SRCError {
         SRCError::new(error, None, false)
     }
+    /// Error for when the schema registry responded the subject, version or id doesn't exist, the
+    /// cause is the body of the response, which contains the error code and message.
+    pub fn not_found(error: &str, cause: Option<String>) -> SRCError {
+        SRCError {
+            not_found: true,
+            ..SRCError::new(error, cause, false)
+        }
+    }
+    /// Whether retrying might succeed, this is the case for failed http calls, timeouts, and when the
+    /// schema registry responds with a server error or too many requests. Errors because of
+    /// invalid configuration, missing schema's, or parsing are not retryable.
+    pub fn is_retryable(&self) -> bool {
+        self.retriable
+    }
+    /// Whether the schema registry responded with not found, for example because the subject, the
+    /// version or the id doesn't exist. Can be used to register a schema when it's absent.
+    pub fn is_not_found(&self) -> bool {
+        self.not_found
+    }
+    /// The error code and message from the schema registry, when the schema registry responded
+    /// with an error and its body could be parsed.
+    pub fn registry_error(&self) -> Option<RegistryError> {
+        self.cause
+            .as_deref()
+            .and_then(|cause| serde_json::from_str(cause).ok())
+    }
     /// Should be called before putting the error in the cache
     pub fn into_cache(self) -> SRCError {
         SRCError {
@@ -72,13 +112,14 @@ impl SRCError {
             cause: self.cause,
             retriable: self.retriable,
             cached: true,
+            not_found: self.not_found,
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::error::SRCError;
+    use crate::error::{RegistryError, SRCError};
 
     #[test]
     fn display_error_no_cause() {
@@ -86,6 +127,33 @@ mod tests {
         assert_eq!(format!("{}", err), "Error: Could not get id from response had no other cause, it\'s retriable: false, it\'s cached: false".to_owned())
     }
 
+    #[test]
+    fn registry_error_from_cause() {
+        let err = SRCError::not_found(
+            "schema registry responded with status 404 Not Found",
+            Some(String::from(
+                r#"{"error_code":40401,"message":"Subject not found"}"#,
+            )),
+        );
+        assert_eq!(
+            err.registry_error(),
+            Some(RegistryError {
+                error_code: 40401,
+                message: String::from("Subject not found")
+            })
+        );
+        let err = SRCError::new(
+            "schema registry responded with status 420",
+            Some(String::from(
+                r#"{"error_code":420,"description":"No mock defined"}"#,
+            )),
+            false,
+        );
+        assert_eq!(err.registry_error().unwrap().message, "No mock defined");
+        let err = SRCError::non_retryable_with_cause("not json", "Could not parse");
+        assert_eq!(err.registry_error(), None);
+    }
+
     #[test]
     fn display_error_with_cause() {
         let err = SRCError::new(
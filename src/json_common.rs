@@ -1,9 +1,27 @@
 use serde_json::value::Value;
 use url::Url;
 use valico::json_schema::validators::ValidationState;
+use valico::json_schema::{SchemaVersion, Scope};
 
 use crate::error::SRCError;
-use crate::schema_registry_common::get_payload;
+use crate::schema_registry_common::{get_payload, RegisteredReference};
+
+/// The json schema draft used to compile schemas. Since the draft is not derived from the `$schema`
+/// of the schema, it needs to be set when schemas depend on keywords from a later draft.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum JsonSchemaDraft {
+    #[default]
+    Draft7,
+    Draft2019_09,
+}
+
+pub(crate) fn scope_for_draft(draft: JsonSchemaDraft) -> Scope {
+    let version = match draft {
+        JsonSchemaDraft::Draft7 => SchemaVersion::Draft7,
+        JsonSchemaDraft::Draft2019_09 => SchemaVersion::Draft2019_09,
+    };
+    Scope::new().set_version(version)
+}
 
 pub(crate) fn handle_validation(
     validation: ValidationState,
@@ -51,9 +69,27 @@ pub(crate) fn fetch_id(def: &Value) -> Option<Url> {
     }
 }
 
-pub(crate) fn fetch_fallback(url: &str, id: u32) -> Url {
+pub(crate) fn fetch_fallback(url: &str, id: u32) -> Result<Url, SRCError> {
     let id = format!("{}/id/{}.json", url, id);
-    Url::parse(&id).unwrap()
+    match Url::parse(&id) {
+        Ok(url) => Ok(url),
+        Err(e) => Err(SRCError::non_retryable_with_cause(
+            e,
+            &format!(
+                "could not create fallback url {} for schema without $id",
+                id
+            ),
+        )),
+    }
+}
+
+/// Gets the url for a referenced schema. The name of the reference can either be a fully qualified
+/// url, or be relative to the url of the schema referring to it, like it would be used in `$ref`.
+pub(crate) fn reference_url(base: &Url, rr: &RegisteredReference) -> Result<Url, SRCError> {
+    match base.join(&rr.name) {
+        Ok(v) => Ok(v),
+        Err(e) => Err(SRCError::non_retryable_with_cause(e, &format!("reference schema with subject {} and version {} has invalid id {}, it has to be a url, or relative to the url of the referring schema", rr.subject, rr.version, rr.name)))
+    }
 }
 
 pub(crate) fn to_value(str: &str) -> Result<Value, SRCError> {
@@ -68,3 +104,49 @@ pub(crate) fn to_value(str: &str) -> Result<Value, SRCError> {
     };
     Ok(value)
 }
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use crate::json_common::{fetch_fallback, reference_url};
+    use crate::schema_registry_common::RegisteredReference;
+
+    #[test]
+    fn fetch_fallback_valid_url() {
+        let url = fetch_fallback("http://localhost:8081", 7).unwrap();
+        assert_eq!("http://localhost:8081/id/7.json", url.as_str())
+    }
+
+    #[test]
+    fn reference_url_absolute_or_relative() {
+        let base = Url::parse("http://www.example.com/test.json").unwrap();
+        let absolute = RegisteredReference {
+            name: String::from("urn:example:result"),
+            subject: String::from("result.json"),
+            version: 1,
+        };
+        let relative = RegisteredReference {
+            name: String::from("result.json"),
+            subject: String::from("result.json"),
+            version: 1,
+        };
+        assert_eq!(
+            "urn:example:result",
+            reference_url(&base, &absolute).unwrap().as_str()
+        );
+        assert_eq!(
+            "http://www.example.com/result.json",
+            reference_url(&base, &relative).unwrap().as_str()
+        );
+    }
+
+    #[test]
+    fn fetch_fallback_invalid_url_is_error() {
+        let error = fetch_fallback("no-scheme", 7).unwrap_err();
+        assert!(error
+            .error
+            .starts_with("could not create fallback url no-scheme/id/7.json"));
+        assert!(!error.retriable)
+    }
+}
@@ -29,9 +29,11 @@ pub mod avro_common;
 pub mod blocking;
 pub mod error;
 #[cfg(feature = "json")]
-mod json_common;
+pub mod json_common;
 #[cfg(any(feature = "proto_decoder", feature = "proto_raw"))]
 mod proto_common_types;
+#[cfg(feature = "proto_decoder")]
+pub mod proto_decoder_common;
 #[cfg(feature = "proto_raw")]
 pub mod proto_raw_common;
 #[cfg(any(feature = "proto_decoder", feature = "proto_raw"))]
@@ -0,0 +1,681 @@
+use bytes::Bytes;
+use integer_encoding::VarIntReader;
+use protofish::context::{Context, EnumRef, MessageInfo, Multiplicity, TypeInfo, ValueType};
+use protofish::decode::{EnumValue, FieldValue, MessageValue, PackedArray, UnknownValue, Value};
+
+use crate::error::SRCError;
+
+/// Replaces the values of the google.protobuf.Timestamp and google.protobuf.Duration well known
+/// types by strings, the same way they are mapped to json. A timestamp becomes an RFC 3339 string
+/// like `2021-01-01T00:00:00.500Z`, a duration a string like `1.500s`. As there is no value for
+/// objects, the google.protobuf.Struct, google.protobuf.Value and google.protobuf.ListValue well
+/// known types become a string with their json, like `{"a":[1.0,null]}`. Protofish doesn't support
+/// map fields, so the struct.proto used should declare the fields of Struct as a repeated
+/// `FieldsEntry` message with a `string key = 1` and a `Value value = 2`, which is the same on
+/// the wire.
+pub fn map_well_known_types(value: MessageValue, context: &Context) -> MessageValue {
+    MessageValue {
+        msg_ref: value.msg_ref,
+        fields: value
+            .fields
+            .into_iter()
+            .map(|f| FieldValue {
+                number: f.number,
+                value: map_value(f.value, context),
+            })
+            .collect(),
+        garbage: value.garbage,
+    }
+}
+
+fn map_value(value: Value, context: &Context) -> Value {
+    match value {
+        Value::Message(m) => match context.resolve_message(m.msg_ref).full_name.as_str() {
+            "google.protobuf.Timestamp" => {
+                let (seconds, nanos) = seconds_and_nanos(&m);
+                Value::String(to_rfc3339(seconds, nanos))
+            }
+            "google.protobuf.Duration" => {
+                let (seconds, nanos) = seconds_and_nanos(&m);
+                Value::String(to_duration(seconds, nanos))
+            }
+            "google.protobuf.Struct" => Value::String(struct_to_json(&m).to_string()),
+            "google.protobuf.Value" => Value::String(value_to_json(&m).to_string()),
+            "google.protobuf.ListValue" => Value::String(list_to_json(&m).to_string()),
+            _ => Value::Message(Box::new(map_well_known_types(*m, context))),
+        },
+        v => v,
+    }
+}
+
+/// Gets the message with the full name from the context, failing when it doesn't exist.
+pub(crate) fn get_message<'c>(
+    context: &'c Context,
+    message_full_name: &str,
+) -> Result<&'c MessageInfo, SRCError> {
+    context.get_message(message_full_name).ok_or_else(|| {
+        SRCError::non_retryable_without_cause(&format!(
+            "Could not find message {} in the schema",
+            message_full_name
+        ))
+    })
+}
+
+/// Decodes the bytes into the message, with the repeated values and oneofs handled like the
+/// protobuf spec requires, see [decode_repeated_values] and [resolve_oneofs].
+pub(crate) fn decode_message(
+    message_info: &MessageInfo,
+    data: &[u8],
+    context: &Context,
+) -> MessageValue {
+    let value = message_info.decode(data, context);
+    resolve_oneofs(decode_repeated_values(value, context), context)
+}
+
+/// Depending on the schema, protofish expects the values of a repeated scalar field to be either
+/// packed or not, and returns values encoded the other way as unknown. As both should be accepted,
+/// and can even be mixed for the same field, this decodes those unknown values. This is also done
+/// for nested messages. As there is no packed array for enums, packed enum values are split into
+/// a field value for each enum value, the same as when they are not packed.
+pub fn decode_repeated_values(value: MessageValue, context: &Context) -> MessageValue {
+    let message_info = context.resolve_message(value.msg_ref);
+    MessageValue {
+        msg_ref: value.msg_ref,
+        fields: value
+            .fields
+            .into_iter()
+            .flat_map(|f| {
+                let value = match f.value {
+                    Value::Message(m) => {
+                        Value::Message(Box::new(decode_repeated_values(*m, context)))
+                    }
+                    Value::Unknown(u) => match message_info.get_field(f.number) {
+                        Some(field)
+                            if field.multiplicity == Multiplicity::Repeated
+                                || field.multiplicity == Multiplicity::RepeatedPacked =>
+                        {
+                            match (u, &field.field_type) {
+                                (
+                                    UnknownValue::VariableLength(bytes),
+                                    ValueType::Enum(enum_ref),
+                                ) => match decode_packed_enums(&bytes, *enum_ref) {
+                                    Some(values) => {
+                                        return values
+                                            .into_iter()
+                                            .map(|v| FieldValue {
+                                                number: f.number,
+                                                value: Value::Enum(v),
+                                            })
+                                            .collect()
+                                    }
+                                    None => Value::Unknown(UnknownValue::VariableLength(bytes)),
+                                },
+                                (u, t) => decode_unknown(u, t),
+                            }
+                        }
+                        _ => Value::Unknown(u),
+                    },
+                    v => v,
+                };
+                vec![FieldValue {
+                    number: f.number,
+                    value,
+                }]
+            })
+            .collect(),
+        garbage: value.garbage,
+    }
+}
+
+fn decode_unknown(value: UnknownValue, value_type: &ValueType) -> Value {
+    match (value, value_type) {
+        (UnknownValue::Varint(v), ValueType::Int32) => Value::Int32(v as i32),
+        (UnknownValue::Varint(v), ValueType::Int64) => Value::Int64(v as i64),
+        (UnknownValue::Varint(v), ValueType::UInt32) => Value::UInt32(v as u32),
+        (UnknownValue::Varint(v), ValueType::UInt64) => Value::UInt64(v as u64),
+        (UnknownValue::Varint(v), ValueType::SInt32) => Value::SInt32(zigzag(v as u64) as i32),
+        (UnknownValue::Varint(v), ValueType::SInt64) => Value::SInt64(zigzag(v as u64)),
+        (UnknownValue::Varint(v), ValueType::Bool) => Value::Bool(v != 0),
+        (UnknownValue::Varint(v), ValueType::Enum(enum_ref)) => Value::Enum(EnumValue {
+            enum_ref: *enum_ref,
+            value: v as i64,
+        }),
+        (UnknownValue::Fixed32(v), ValueType::Fixed32) => Value::Fixed32(v),
+        (UnknownValue::Fixed32(v), ValueType::SFixed32) => Value::SFixed32(v as i32),
+        (UnknownValue::Fixed32(v), ValueType::Float) => Value::Float(f32::from_bits(v)),
+        (UnknownValue::Fixed64(v), ValueType::Fixed64) => Value::Fixed64(v),
+        (UnknownValue::Fixed64(v), ValueType::SFixed64) => Value::SFixed64(v as i64),
+        (UnknownValue::Fixed64(v), ValueType::Double) => Value::Double(f64::from_bits(v)),
+        (UnknownValue::VariableLength(bytes), t) => match decode_packed(&bytes, t) {
+            Some(packed) => Value::Packed(packed),
+            None => Value::Unknown(UnknownValue::VariableLength(bytes)),
+        },
+        (v, _) => Value::Unknown(v),
+    }
+}
+
+fn decode_packed(bytes: &[u8], value_type: &ValueType) -> Option<PackedArray> {
+    match value_type {
+        ValueType::Int32 => Some(PackedArray::Int32(map_varints(bytes, |v| v as i32)?)),
+        ValueType::Int64 => Some(PackedArray::Int64(map_varints(bytes, |v| v as i64)?)),
+        ValueType::UInt32 => Some(PackedArray::UInt32(map_varints(bytes, |v| v as u32)?)),
+        ValueType::UInt64 => Some(PackedArray::UInt64(map_varints(bytes, |v| v)?)),
+        ValueType::SInt32 => Some(PackedArray::SInt32(map_varints(bytes, |v| {
+            zigzag(v) as i32
+        })?)),
+        ValueType::SInt64 => Some(PackedArray::SInt64(map_varints(bytes, zigzag)?)),
+        ValueType::Bool => Some(PackedArray::Bool(map_varints(bytes, |v| v != 0)?)),
+        ValueType::Fixed32 => Some(PackedArray::Fixed32(map_fixed32(bytes, |v| v)?)),
+        ValueType::SFixed32 => Some(PackedArray::SFixed32(map_fixed32(bytes, |v| v as i32)?)),
+        ValueType::Float => Some(PackedArray::Float(map_fixed32(bytes, f32::from_bits)?)),
+        ValueType::Fixed64 => Some(PackedArray::Fixed64(map_fixed64(bytes, |v| v)?)),
+        ValueType::SFixed64 => Some(PackedArray::SFixed64(map_fixed64(bytes, |v| v as i64)?)),
+        ValueType::Double => Some(PackedArray::Double(map_fixed64(bytes, f64::from_bits)?)),
+        _ => None,
+    }
+}
+
+fn decode_packed_enums(bytes: &[u8], enum_ref: EnumRef) -> Option<Vec<EnumValue>> {
+    map_varints(bytes, |v| EnumValue {
+        enum_ref,
+        value: v as i64,
+    })
+}
+
+fn map_varints<T>(mut bytes: &[u8], f: impl Fn(u64) -> T) -> Option<Vec<T>> {
+    let mut values = Vec::new();
+    while !bytes.is_empty() {
+        let v: u64 = bytes.read_varint().ok()?;
+        values.push(f(v));
+    }
+    Some(values)
+}
+
+fn map_fixed32<T>(bytes: &[u8], f: impl Fn(u32) -> T) -> Option<Vec<T>> {
+    if !bytes.len().is_multiple_of(4) {
+        return None;
+    }
+    Some(
+        bytes
+            .chunks_exact(4)
+            .map(|c| f(u32::from_le_bytes([c[0], c[1], c[2], c[3]])))
+            .collect(),
+    )
+}
+
+fn map_fixed64<T>(bytes: &[u8], f: impl Fn(u64) -> T) -> Option<Vec<T>> {
+    if !bytes.len().is_multiple_of(8) {
+        return None;
+    }
+    Some(
+        bytes
+            .chunks_exact(8)
+            .map(|c| {
+                f(u64::from_le_bytes([
+                    c[0], c[1], c[2], c[3], c[4], c[5], c[6], c[7],
+                ]))
+            })
+            .collect(),
+    )
+}
+
+fn zigzag(v: u64) -> i64 {
+    ((v >> 1) as i64) ^ -((v & 1) as i64)
+}
+
+/// Keeps only the last field of each oneof in the message, as only one of them can be set. When
+/// the bytes contain multiple fields of the same oneof, the last one wins, like with other
+/// protobuf implementations. This is also done for nested messages.
+pub fn resolve_oneofs(value: MessageValue, context: &Context) -> MessageValue {
+    let message_info = context.resolve_message(value.msg_ref);
+    let fields: Vec<FieldValue> = value
+        .fields
+        .iter()
+        .enumerate()
+        .filter(|(i, f)| {
+            match message_info
+                .oneofs
+                .iter()
+                .find(|o| o.fields.contains(&f.number))
+            {
+                Some(oneof) => !value.fields[i + 1..]
+                    .iter()
+                    .any(|later| oneof.fields.contains(&later.number)),
+                None => true,
+            }
+        })
+        .map(|(_, f)| f.clone())
+        .collect();
+    MessageValue {
+        msg_ref: value.msg_ref,
+        fields: fields
+            .into_iter()
+            .map(|f| FieldValue {
+                number: f.number,
+                value: match f.value {
+                    Value::Message(m) => Value::Message(Box::new(resolve_oneofs(*m, context))),
+                    v => v,
+                },
+            })
+            .collect(),
+        garbage: value.garbage,
+    }
+}
+
+/// Adds the default value for each proto3 scalar and enum field that's not in the message, as
+/// these have no explicit presence, and are not send when they have the default value. Fields
+/// marked optional, part of a oneof, repeated, or of a message type keep being absent, as their
+/// absence has meaning. Nested messages get their default values added as well.
+pub fn add_default_values(value: MessageValue, context: &Context) -> MessageValue {
+    let mut fields: Vec<FieldValue> = value
+        .fields
+        .into_iter()
+        .map(|f| FieldValue {
+            number: f.number,
+            value: match f.value {
+                Value::Message(m) => Value::Message(Box::new(add_default_values(*m, context))),
+                v => v,
+            },
+        })
+        .collect();
+    let message_info = context.resolve_message(value.msg_ref);
+    for field in message_info.iter_fields() {
+        if field.multiplicity != Multiplicity::Single
+            || field.oneof.is_some()
+            || fields.iter().any(|f| f.number == field.number)
+        {
+            continue;
+        }
+        if let Some(default) = default_value(&field.field_type) {
+            fields.push(FieldValue {
+                number: field.number,
+                value: default,
+            })
+        }
+    }
+    MessageValue {
+        msg_ref: value.msg_ref,
+        fields,
+        garbage: value.garbage,
+    }
+}
+
+fn default_value(value_type: &ValueType) -> Option<Value> {
+    match value_type {
+        ValueType::Double => Some(Value::Double(0.0)),
+        ValueType::Float => Some(Value::Float(0.0)),
+        ValueType::Int32 => Some(Value::Int32(0)),
+        ValueType::Int64 => Some(Value::Int64(0)),
+        ValueType::UInt32 => Some(Value::UInt32(0)),
+        ValueType::UInt64 => Some(Value::UInt64(0)),
+        ValueType::SInt32 => Some(Value::SInt32(0)),
+        ValueType::SInt64 => Some(Value::SInt64(0)),
+        ValueType::Fixed32 => Some(Value::Fixed32(0)),
+        ValueType::Fixed64 => Some(Value::Fixed64(0)),
+        ValueType::SFixed32 => Some(Value::SFixed32(0)),
+        ValueType::SFixed64 => Some(Value::SFixed64(0)),
+        ValueType::Bool => Some(Value::Bool(false)),
+        ValueType::String => Some(Value::String(String::new())),
+        ValueType::Bytes => Some(Value::Bytes(Bytes::new())),
+        ValueType::Enum(enum_ref) => Some(Value::Enum(EnumValue {
+            enum_ref: *enum_ref,
+            value: 0,
+        })),
+        ValueType::Message(_) => None,
+    }
+}
+
+/// Gets the name of the enum value as defined in the schema, none when the number is not defined,
+/// which can happen when the producer used a newer version of the schema.
+pub fn enum_name<'c>(value: &EnumValue, context: &'c Context) -> Option<&'c str> {
+    context
+        .resolve_enum(value.enum_ref)
+        .get_field_by_value(value.value)
+        .map(|f| f.name.as_str())
+}
+
+/// Gets the name of the number for the enum with the full name, like `nl.openweb.data.Atype`. None
+/// when the enum doesn't exist, or the number is not defined, so it can be used to validate
+/// values against the allowed values.
+pub fn enum_name_by_type<'c>(
+    context: &'c Context,
+    full_name: &str,
+    number: i64,
+) -> Option<&'c str> {
+    match context.get_type(full_name) {
+        Some(TypeInfo::Enum(e)) => e.get_field_by_value(number).map(|f| f.name.as_str()),
+        _ => None,
+    }
+}
+
+fn seconds_and_nanos(value: &MessageValue) -> (i64, i32) {
+    let mut seconds = 0;
+    let mut nanos = 0;
+    for f in &value.fields {
+        match (f.number, &f.value) {
+            (1, Value::Int64(v)) => seconds = *v,
+            (2, Value::Int32(v)) => nanos = *v,
+            _ => (),
+        }
+    }
+    (seconds, nanos)
+}
+
+fn struct_to_json(value: &MessageValue) -> serde_json::Value {
+    let mut map = serde_json::Map::new();
+    for f in &value.fields {
+        if let (1, Value::Message(entry)) = (f.number, &f.value) {
+            let mut key = String::new();
+            let mut json = serde_json::Value::Null;
+            for e in &entry.fields {
+                match (e.number, &e.value) {
+                    (1, Value::String(k)) => key = k.clone(),
+                    (2, Value::Message(v)) => json = value_to_json(v),
+                    _ => (),
+                }
+            }
+            map.insert(key, json);
+        }
+    }
+    serde_json::Value::Object(map)
+}
+
+fn value_to_json(value: &MessageValue) -> serde_json::Value {
+    let mut json = serde_json::Value::Null;
+    for f in &value.fields {
+        match (f.number, &f.value) {
+            (1, _) => json = serde_json::Value::Null,
+            (2, Value::Double(v)) => {
+                json = serde_json::Number::from_f64(*v)
+                    .map(serde_json::Value::Number)
+                    .unwrap_or(serde_json::Value::Null)
+            }
+            (3, Value::String(v)) => json = serde_json::Value::String(v.clone()),
+            (4, Value::Bool(v)) => json = serde_json::Value::Bool(*v),
+            (5, Value::Message(m)) => json = struct_to_json(m),
+            (6, Value::Message(m)) => json = list_to_json(m),
+            _ => (),
+        }
+    }
+    json
+}
+
+fn list_to_json(value: &MessageValue) -> serde_json::Value {
+    serde_json::Value::Array(
+        value
+            .fields
+            .iter()
+            .filter_map(|f| match (f.number, &f.value) {
+                (1, Value::Message(m)) => Some(value_to_json(m)),
+                _ => None,
+            })
+            .collect(),
+    )
+}
+
+fn fraction(nanos: u32) -> String {
+    if nanos == 0 {
+        String::new()
+    } else if nanos.is_multiple_of(1_000_000) {
+        format!(".{:03}", nanos / 1_000_000)
+    } else if nanos.is_multiple_of(1_000) {
+        format!(".{:06}", nanos / 1_000)
+    } else {
+        format!(".{:09}", nanos)
+    }
+}
+
+fn to_rfc3339(seconds: i64, nanos: i32) -> String {
+    let days = seconds.div_euclid(86_400);
+    let second_of_day = seconds.rem_euclid(86_400);
+    // civil from days, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}{}Z",
+        year,
+        month,
+        day,
+        second_of_day / 3_600,
+        second_of_day % 3_600 / 60,
+        second_of_day % 60,
+        fraction(nanos.unsigned_abs())
+    )
+}
+
+fn to_duration(seconds: i64, nanos: i32) -> String {
+    let sign = if seconds < 0 || nanos < 0 { "-" } else { "" };
+    format!(
+        "{}{}{}s",
+        sign,
+        seconds.unsigned_abs(),
+        fraction(nanos.unsigned_abs())
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::proto_decoder_common::{
+        add_default_values, decode_message, decode_repeated_values, enum_name, enum_name_by_type,
+        map_well_known_types, resolve_oneofs, to_duration, to_rfc3339,
+    };
+    use protofish::context::Context;
+    use protofish::decode::{PackedArray, Value};
+
+    #[test]
+    fn rfc3339() {
+        assert_eq!("1970-01-01T00:00:00Z", to_rfc3339(0, 0));
+        assert_eq!(
+            "2021-01-01T00:00:00.500Z",
+            to_rfc3339(1_609_459_200, 500_000_000)
+        );
+        assert_eq!(
+            "2024-02-29T13:14:15.000001Z",
+            to_rfc3339(1_709_212_455, 1_000)
+        );
+        assert_eq!("1969-12-31T23:59:59.000000001Z", to_rfc3339(-1, 1));
+    }
+
+    #[test]
+    fn duration() {
+        assert_eq!("0s", to_duration(0, 0));
+        assert_eq!("1.500s", to_duration(1, 500_000_000));
+        assert_eq!("-1.500s", to_duration(-1, -500_000_000));
+        assert_eq!("-0.000000010s", to_duration(0, -10));
+    }
+
+    #[test]
+    fn struct_as_json() {
+        let context = Context::parse([
+            r#"syntax = "proto3";
+package google.protobuf;
+message Struct {
+  message FieldsEntry { string key = 1; Value value = 2; }
+  repeated FieldsEntry fields = 1;
+}
+message Value {
+  oneof kind {
+    NullValue null_value = 1;
+    double number_value = 2;
+    string string_value = 3;
+    bool bool_value = 4;
+    Struct struct_value = 5;
+    ListValue list_value = 6;
+  }
+}
+enum NullValue { NULL_VALUE = 0; }
+message ListValue { repeated Value values = 1; }"#,
+            r#"syntax = "proto3";
+package nl.openweb.data;
+import "google/protobuf/struct.proto";
+message Holder { google.protobuf.Struct s = 1; google.protobuf.Value v = 2; }"#,
+        ])
+        .unwrap();
+        let message_info = context.get_message("nl.openweb.data.Holder").unwrap();
+        // s = {"a": [1.0, null]}, v = "x"
+        let bytes = [
+            10, 24, 10, 22, 10, 1, 97, 18, 17, 50, 15, 10, 9, 17, 0, 0, 0, 0, 0, 0, 240, 63, 10, 2,
+            8, 0, 18, 3, 26, 1, 120,
+        ];
+
+        let value = map_well_known_types(decode_message(message_info, &bytes, &context), &context);
+
+        assert_eq!(
+            Value::String(String::from(r#"{"a":[1.0,null]}"#)),
+            value.fields[0].value
+        );
+        assert_eq!(Value::String(String::from(r#""x""#)), value.fields[1].value);
+    }
+
+    #[test]
+    fn default_values() {
+        let context = Context::parse([r#"syntax = "proto3";
+package nl.openweb.data;
+enum Color { RED = 0; GREEN = 1; }
+message Inner { int32 x = 1; }
+message Outer {
+  int32 a = 1;
+  string b = 2;
+  optional int64 c = 3;
+  Inner d = 4;
+  repeated int32 e = 5;
+  oneof o { int32 f = 6; }
+  Color g = 7;
+}"#])
+        .unwrap();
+        let message_info = context.get_message("nl.openweb.data.Outer").unwrap();
+
+        let value = add_default_values(message_info.decode(&[34, 0], &context), &context);
+
+        let numbers: Vec<u64> = value.fields.iter().map(|f| f.number).collect();
+        assert_eq!(vec![4, 1, 2, 7], numbers);
+        match &value.fields[0].value {
+            Value::Message(m) => assert_eq!(Value::Int32(0), m.fields[0].value),
+            v => panic!("Other value: {:?} than expected Message", v),
+        }
+        assert_eq!(Value::Int32(0), value.fields[1].value);
+        assert_eq!(Value::String(String::new()), value.fields[2].value);
+        match &value.fields[3].value {
+            Value::Enum(e) => assert_eq!(0, e.value),
+            v => panic!("Other value: {:?} than expected Enum", v),
+        }
+    }
+
+    #[test]
+    fn oneof_last_wins() {
+        let context = Context::parse([r#"syntax = "proto3";
+package nl.openweb.data;
+message Choice {
+  int32 a = 1;
+  oneof o { int32 b = 2; string c = 3; }
+}"#])
+        .unwrap();
+        let message_info = context.get_message("nl.openweb.data.Choice").unwrap();
+
+        let value = resolve_oneofs(
+            message_info.decode(&[16, 5, 8, 1, 26, 1, 120], &context),
+            &context,
+        );
+
+        let numbers: Vec<u64> = value.fields.iter().map(|f| f.number).collect();
+        assert_eq!(vec![1, 3], numbers);
+        assert_eq!(Value::String(String::from("x")), value.fields[1].value);
+    }
+
+    #[test]
+    fn enum_names() {
+        let context = Context::parse([r#"syntax = "proto3";
+package nl.openweb.data;
+enum Color { RED = 0; GREEN = 1; }
+message Paint { Color color = 1; }"#])
+        .unwrap();
+        let message_info = context.get_message("nl.openweb.data.Paint").unwrap();
+
+        let value = message_info.decode(&[8, 1], &context);
+        match &value.fields[0].value {
+            Value::Enum(e) => assert_eq!(Some("GREEN"), enum_name(e, &context)),
+            v => panic!("Other value: {:?} than expected Enum", v),
+        }
+        assert_eq!(
+            Some("RED"),
+            enum_name_by_type(&context, "nl.openweb.data.Color", 0)
+        );
+        assert_eq!(
+            None,
+            enum_name_by_type(&context, "nl.openweb.data.Color", 2)
+        );
+        assert_eq!(
+            None,
+            enum_name_by_type(&context, "nl.openweb.data.Paint", 0)
+        );
+    }
+
+    #[test]
+    fn packed_and_unpacked_repeated_values() {
+        let context = Context::parse([r#"syntax = "proto3";
+package nl.openweb.data;
+message Numbers {
+  repeated int32 xs = 1;
+  repeated sint64 ss = 2 [packed = false];
+  repeated double ds = 3;
+}"#])
+        .unwrap();
+        let message_info = context.get_message("nl.openweb.data.Numbers").unwrap();
+
+        let value = decode_repeated_values(
+            message_info.decode(
+                &[
+                    10, 2, 1, 2, 8, 3, 18, 2, 3, 4, 25, 0, 0, 0, 0, 0, 0, 240, 63,
+                ],
+                &context,
+            ),
+            &context,
+        );
+
+        let values: Vec<Value> = value.fields.into_iter().map(|f| f.value).collect();
+        assert_eq!(
+            vec![
+                Value::Packed(PackedArray::Int32(vec![1, 2])),
+                Value::Int32(3),
+                Value::Packed(PackedArray::SInt64(vec![-2, 2])),
+                Value::Double(1.0),
+            ],
+            values
+        );
+    }
+
+    #[test]
+    fn packed_enum_values() {
+        let context = Context::parse([r#"syntax = "proto3";
+package nl.openweb.data;
+enum Color { RED = 0; GREEN = 1; BLUE = 2; }
+message Colors {
+  repeated Color cs = 1;
+}"#])
+        .unwrap();
+        let message_info = context.get_message("nl.openweb.data.Colors").unwrap();
+
+        let value = decode_repeated_values(
+            message_info.decode(&[10, 2, 2, 0, 8, 1], &context),
+            &context,
+        );
+
+        let values: Vec<(u64, i64)> = value
+            .fields
+            .into_iter()
+            .map(|f| match f.value {
+                Value::Enum(e) => (f.number, e.value),
+                v => panic!("expected an enum value, got {:?}", v),
+            })
+            .collect();
+        assert_eq!(vec![(1, 2), (1, 0), (1, 1)], values);
+    }
+}
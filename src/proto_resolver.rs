@@ -175,6 +175,14 @@ fn same_vec(first: &[i32], second: &[i32]) -> bool {
     true
 }
 
+/// Gets the imports of all the files, to know which common schemas should be added.
+pub(crate) fn imports_of_all(files: &[String]) -> Vec<String> {
+    files
+        .iter()
+        .flat_map(|f| ResolverHelper::new(f).imports)
+        .collect()
+}
+
 pub fn to_index_and_data(bytes: &[u8]) -> (Vec<i32>, Vec<u8>) {
     if bytes[0] == 0 {
         (vec![0], bytes[1..].to_vec())